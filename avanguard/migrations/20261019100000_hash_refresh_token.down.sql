@@ -0,0 +1,3 @@
+DROP INDEX refreshtoken_token_hash;
+DELETE FROM "refreshtoken";
+ALTER TABLE "refreshtoken" RENAME COLUMN token_hash TO token;
@@ -0,0 +1,4 @@
+-- Existing tokens are stored in plaintext, invalidate them all.
+DELETE FROM "refreshtoken";
+ALTER TABLE "refreshtoken" RENAME COLUMN token TO token_hash;
+CREATE UNIQUE INDEX refreshtoken_token_hash ON "refreshtoken" (token_hash);
@@ -0,0 +1,5 @@
+CREATE TABLE "rate_limit" (
+    key text PRIMARY KEY,
+    window_start timestamp without time zone NOT NULL,
+    hits integer NOT NULL
+);
@@ -0,0 +1,3 @@
+DROP TABLE "failed_login_ip";
+ALTER TABLE "wallet" DROP COLUMN locked_until;
+ALTER TABLE "wallet" DROP COLUMN failed_login_attempts;
@@ -0,0 +1,8 @@
+ALTER TABLE "wallet" ADD COLUMN failed_login_attempts integer NOT NULL DEFAULT 0;
+ALTER TABLE "wallet" ADD COLUMN locked_until timestamp without time zone NULL;
+CREATE TABLE "failed_login_ip" (
+    ip text PRIMARY KEY,
+    failed_attempts integer NOT NULL,
+    last_attempt_at timestamp without time zone NOT NULL,
+    locked_until timestamp without time zone NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE "refreshtoken" DROP COLUMN login_event_id;
+DROP TABLE "login_event";
@@ -0,0 +1,16 @@
+CREATE TABLE "login_event" (
+    id bigserial PRIMARY KEY,
+    wallet_id bigint NOT NULL,
+    timestamp timestamp without time zone NOT NULL,
+    ip text NULL,
+    user_agent text NULL,
+    client_id text NOT NULL,
+    method text NOT NULL,
+    outcome text NOT NULL,
+    FOREIGN KEY(wallet_id) REFERENCES "wallet"(id) ON DELETE CASCADE
+);
+CREATE INDEX login_event_wallet_id_timestamp ON "login_event" (wallet_id, timestamp);
+CREATE INDEX login_event_timestamp ON "login_event" (timestamp);
+ALTER TABLE "refreshtoken" ADD COLUMN login_event_id bigint NULL
+    REFERENCES "login_event"(id) ON DELETE SET NULL;
+CREATE INDEX refreshtoken_login_event_id ON "refreshtoken" (login_event_id);
@@ -0,0 +1,23 @@
+CREATE TABLE "webhook" (
+    id bigserial PRIMARY KEY,
+    url text NOT NULL,
+    events text[] NOT NULL,
+    secret text NOT NULL,
+    enabled boolean NOT NULL,
+    created_at timestamp without time zone NOT NULL
+);
+CREATE TABLE "webhook_delivery" (
+    id bigserial PRIMARY KEY,
+    webhook_id bigint NOT NULL,
+    event text NOT NULL,
+    payload text NOT NULL,
+    created_at timestamp without time zone NOT NULL,
+    attempts integer NOT NULL DEFAULT 0,
+    next_attempt_at timestamp without time zone NOT NULL,
+    last_error text NULL,
+    delivered_at timestamp without time zone NULL,
+    failed_at timestamp without time zone NULL,
+    FOREIGN KEY(webhook_id) REFERENCES "webhook"(id) ON DELETE CASCADE
+);
+CREATE INDEX webhook_delivery_pending ON "webhook_delivery" (next_attempt_at)
+    WHERE delivered_at IS NULL AND failed_at IS NULL;
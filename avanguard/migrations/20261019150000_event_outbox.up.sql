@@ -0,0 +1,9 @@
+CREATE TABLE "event_outbox" (
+    id bigserial PRIMARY KEY,
+    event text NOT NULL,
+    key text NOT NULL,
+    payload text NOT NULL,
+    created_at timestamp without time zone NOT NULL,
+    published_at timestamp without time zone NULL
+);
+CREATE INDEX event_outbox_pending ON "event_outbox" (id) WHERE published_at IS NULL;
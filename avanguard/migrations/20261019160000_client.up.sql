@@ -0,0 +1,7 @@
+CREATE TABLE "client" (
+    id bigserial PRIMARY KEY,
+    client_id text NOT NULL UNIQUE,
+    name text NOT NULL,
+    secret text NOT NULL,
+    created_at timestamp without time zone NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE "wallet" ADD COLUMN disabled_at timestamp without time zone NULL;
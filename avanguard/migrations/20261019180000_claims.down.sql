@@ -0,0 +1,2 @@
+ALTER TABLE "refreshtoken" DROP COLUMN scope;
+DROP TABLE "static_claims";
@@ -0,0 +1,6 @@
+CREATE TABLE "static_claims" (
+    address text PRIMARY KEY,
+    claims jsonb NOT NULL,
+    updated_at timestamp without time zone NOT NULL
+);
+ALTER TABLE "refreshtoken" ADD COLUMN scope text NOT NULL DEFAULT '';
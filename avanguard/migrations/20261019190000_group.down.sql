@@ -0,0 +1,2 @@
+DROP TABLE "wallet_group";
+DROP TABLE "group";
@@ -0,0 +1,12 @@
+CREATE TABLE "group" (
+    id bigserial PRIMARY KEY,
+    name text NOT NULL UNIQUE,
+    created_at timestamp without time zone NOT NULL
+);
+CREATE TABLE "wallet_group" (
+    wallet_id bigint NOT NULL REFERENCES "wallet"(id) ON DELETE CASCADE,
+    group_id bigint NOT NULL REFERENCES "group"(id) ON DELETE CASCADE,
+    created_at timestamp without time zone NOT NULL,
+    PRIMARY KEY (wallet_id, group_id)
+);
+CREATE INDEX wallet_group_group_id ON "wallet_group" (group_id);
@@ -0,0 +1 @@
+ALTER TABLE "wallet" DROP COLUMN last_failed_login_at;
@@ -0,0 +1 @@
+ALTER TABLE "wallet" ADD COLUMN last_failed_login_at timestamp without time zone NULL;
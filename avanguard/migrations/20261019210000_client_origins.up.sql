@@ -0,0 +1 @@
+ALTER TABLE "client" ADD COLUMN allowed_origins text[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,221 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    net::UdpSocket,
+    os::unix::net::UnixDatagram,
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use actix_web::{http::header, HttpRequest};
+use chrono::{NaiveDateTime, SecondsFormat, Utc};
+
+use crate::{state::AppState, telemetry::current_request_id, Config};
+
+/// Local syslog socket.
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+/// Syslog priority: facility `auth` (4), severity `info` (6).
+const SYSLOG_PRIORITY: u8 = 4 * 8 + 6;
+
+/// Destination of security audit events.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditTarget {
+    /// Append JSON lines to a file.
+    File(PathBuf),
+    /// Send to local syslog daemon over `/dev/log`.
+    Syslog,
+    /// Send to remote syslog server over UDP.
+    RemoteSyslog(String),
+}
+
+impl fmt::Display for AuditTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "file:{}", path.display()),
+            Self::Syslog => f.write_str("syslog"),
+            Self::RemoteSyslog(address) => write!(f, "syslog://{address}"),
+        }
+    }
+}
+
+impl FromStr for AuditTarget {
+    type Err = String;
+
+    fn from_str(target: &str) -> Result<Self, Self::Err> {
+        if target == "syslog" {
+            Ok(Self::Syslog)
+        } else if let Some(address) = target.strip_prefix("syslog://") {
+            Ok(Self::RemoteSyslog(address.into()))
+        } else if let Some(path) = target.strip_prefix("file:") {
+            Ok(Self::File(path.into()))
+        } else {
+            Err(format!(
+                "invalid audit log target {target}, expected file:<path>, syslog or syslog://<host>:<port>"
+            ))
+        }
+    }
+}
+
+/// Security relevant event.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    ChallengeIssued,
+    LoginSucceeded,
+    LoginFailed { reason: String },
+    TokenRefreshed,
+    TokenRevoked { count: u64 },
+    WalletLocked { locked_until: NaiveDateTime },
+    AdminAction { action: String },
+}
+
+/// Audit event together with information about the request which caused it.
+#[derive(Debug, Serialize)]
+pub struct AuditRecord<'a> {
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: &'a AuditEvent,
+    pub wallet: Option<&'a str>,
+    pub client_id: &'a str,
+    pub ip: Option<String>,
+    pub user_agent: Option<&'a str>,
+    pub request_id: Option<String>,
+}
+
+enum Sink {
+    File(File),
+    Syslog(UnixDatagram),
+    RemoteSyslog(UdpSocket),
+}
+
+impl Sink {
+    fn open(target: &AuditTarget) -> io::Result<Self> {
+        match target {
+            AuditTarget::File(path) => Ok(Self::File(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            AuditTarget::Syslog => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(SYSLOG_SOCKET)?;
+                Ok(Self::Syslog(socket))
+            }
+            AuditTarget::RemoteSyslog(address) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(address)?;
+                Ok(Self::RemoteSyslog(socket))
+            }
+        }
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        match self {
+            Self::File(file) => writeln!(file, "{line}"),
+            Self::Syslog(socket) => socket.send(syslog_message(line).as_bytes()).map(|_| ()),
+            Self::RemoteSyslog(socket) => socket.send(syslog_message(line).as_bytes()).map(|_| ()),
+        }
+    }
+}
+
+/// Format RFC 5424 syslog message.
+fn syslog_message(message: &str) -> String {
+    format!(
+        "<{SYSLOG_PRIORITY}>1 {} - avanguard {} audit - {message}",
+        Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        std::process::id(),
+    )
+}
+
+/// Writer of security audit events, disabled if no target is configured.
+pub struct AuditLog {
+    sink: Option<Mutex<Sink>>,
+}
+
+impl AuditLog {
+    pub fn open(target: Option<&AuditTarget>) -> io::Result<Self> {
+        let sink = match target {
+            Some(target) => Some(Mutex::new(Sink::open(target)?)),
+            None => None,
+        };
+        Ok(Self { sink })
+    }
+
+    /// Record event caused by `req`, concerning `wallet`.
+    pub fn log(
+        &self,
+        req: &HttpRequest,
+        app_state: &AppState,
+        wallet: Option<&str>,
+        event: AuditEvent,
+    ) {
+        if self.sink.is_none() {
+            return;
+        }
+        self.write(&AuditRecord {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            event: &event,
+            wallet,
+            client_id: &app_state.config.client_id,
+            ip: app_state
+                .rate_limiter
+                .client_ip(req)
+                .map(|ip| ip.to_string()),
+            user_agent: req
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok()),
+            request_id: current_request_id(),
+        });
+    }
+
+    /// Record event caused by administrative command, concerning `wallet`.
+    pub fn log_command(&self, config: &Config, wallet: Option<&str>, event: AuditEvent) {
+        self.write(&AuditRecord {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            event: &event,
+            wallet,
+            client_id: &config.client_id,
+            ip: None,
+            user_agent: None,
+            request_id: None,
+        });
+    }
+
+    fn write(&self, record: &AuditRecord) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+        let line = match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(err) => {
+                log::error!("Failed to serialize audit event {:?}: {err}", record.event);
+                return;
+            }
+        };
+        let mut sink = sink.lock().expect("audit log lock poisoned");
+        if let Err(err) = sink.write(&line) {
+            log::error!("Failed to write audit event {line}: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_audit_target() {
+        assert_eq!(
+            "file:/var/log/audit.log".parse(),
+            Ok(AuditTarget::File("/var/log/audit.log".into()))
+        );
+        assert_eq!("syslog".parse(), Ok(AuditTarget::Syslog));
+        assert_eq!(
+            "syslog://localhost:514".parse(),
+            Ok(AuditTarget::RemoteSyslog("localhost:514".into()))
+        );
+        assert!("/var/log/audit.log".parse::<AuditTarget>().is_err());
+    }
+}
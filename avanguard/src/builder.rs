@@ -0,0 +1,208 @@
+use std::sync::Arc;
+
+use actix_web::{web, Scope};
+use openidconnect::url::Url;
+
+use crate::{
+    claims::ClaimsProvider,
+    db::DbPool,
+    events::EventHook,
+    http::{legacy_service, metrics_endpoints, probe_endpoints, versioned_service},
+    state::{AppState, ClientCredentials},
+    Config, ConfigError,
+};
+
+/// Avanguard endpoints which can be mounted in any Actix Web application.
+///
+/// ```no_run
+/// # async fn example(pool: avanguard::db::DbPool) -> Result<(), Box<dyn std::error::Error>> {
+/// use actix_web::{App, HttpServer};
+/// use avanguard::Avanguard;
+///
+/// let avanguard = Avanguard::builder()
+///     .issuer("https://auth.example.com".parse()?)
+///     .client("app", "client secret of at least 32 characters")
+///     .refresh_token_secret("refresh token secret of at least 32 characters")
+///     .store(pool)
+///     .build()?;
+/// HttpServer::new(move || App::new().service(avanguard.scope("/auth")))
+///     .bind(("0.0.0.0", 8080))?
+///     .run()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Avanguard {
+    state: web::Data<AppState>,
+    legacy_api: bool,
+    metrics: bool,
+    health_probes: bool,
+}
+
+impl Avanguard {
+    #[must_use]
+    pub fn builder() -> AvanguardBuilder {
+        AvanguardBuilder::default()
+    }
+
+    /// State shared by handlers, e.g. for [`cors`](crate::cors::cors) middleware
+    /// or background tasks.
+    #[must_use]
+    pub fn state(&self) -> web::Data<AppState> {
+        self.state.clone()
+    }
+
+    /// Scope with Avanguard endpoints under `path`. Called in application factory, so state,
+    /// including in-memory rate limits, is shared by all workers.
+    #[must_use]
+    pub fn scope(&self, path: &str) -> Scope {
+        let mut scope = web::scope(path)
+            .app_data(self.state.clone())
+            .configure(versioned_service);
+        if self.health_probes {
+            scope = scope.configure(probe_endpoints);
+        }
+        if self.metrics {
+            scope = scope.configure(metrics_endpoints);
+        }
+        if self.legacy_api {
+            scope = scope.configure(legacy_service);
+        }
+        scope
+    }
+}
+
+/// Builder of [`Avanguard`]. Options not set explicitly have default [`Config`] values.
+#[derive(Default)]
+pub struct AvanguardBuilder {
+    config: Config,
+    store: Option<DbPool>,
+    clients: Vec<ClientCredentials>,
+    hooks: Vec<Arc<dyn EventHook>>,
+    claims_providers: Vec<Arc<dyn ClaimsProvider>>,
+    legacy_api: bool,
+    metrics: bool,
+    health_probes: bool,
+}
+
+impl AvanguardBuilder {
+    /// Base configuration, replacing values set before.
+    #[must_use]
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// URL used as issuer of tokens.
+    #[must_use]
+    pub fn issuer(mut self, issuer_url: Url) -> Self {
+        self.config.issuer_url = issuer_url;
+        self
+    }
+
+    /// Client tokens are issued to if requests don't name one, and the secret they are signed with.
+    #[must_use]
+    pub fn client(mut self, client_id: &str, secret: &str) -> Self {
+        self.config.client_id = client_id.into();
+        self.config.client_secret = secret.into();
+        self
+    }
+
+    /// Additional client, selected with `client_id` in login requests.
+    #[must_use]
+    pub fn add_client(mut self, client_id: &str, secret: &str) -> Self {
+        self.clients.push(ClientCredentials {
+            client_id: client_id.into(),
+            secret: secret.into(),
+        });
+        self
+    }
+
+    /// Secret used to hash refresh tokens before storing them.
+    #[must_use]
+    pub fn refresh_token_secret(mut self, secret: &str) -> Self {
+        self.config.refresh_token_secret = secret.into();
+        self
+    }
+
+    /// Token required by admin API, which is disabled if not set.
+    #[must_use]
+    pub fn admin_token(mut self, token: &str) -> Self {
+        self.config.admin_token = Some(token.into());
+        self
+    }
+
+    /// Postgres database wallets, tokens and events are stored in. Migrations must be applied,
+    /// e.g. with [`migrate`](crate::db::migrate).
+    #[must_use]
+    pub fn store(mut self, pool: DbPool) -> Self {
+        self.store = Some(pool);
+        self
+    }
+
+    /// Message included in challenges.
+    #[must_use]
+    pub fn challenge_template(mut self, template: &str) -> Self {
+        self.config.challenge_template = Some(template.into());
+        self
+    }
+
+    /// Hook notified about authentication events.
+    #[must_use]
+    pub fn hook(mut self, hook: impl EventHook + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Source of custom claims added to tokens. Claims of providers added later override
+    /// claims of the same name added earlier.
+    #[must_use]
+    pub fn claims_provider(mut self, provider: impl ClaimsProvider + 'static) -> Self {
+        self.claims_providers.push(Arc::new(provider));
+        self
+    }
+
+    /// Also serve endpoints at paths used before versioning, with deprecation headers.
+    #[must_use]
+    pub fn legacy_api(mut self, enabled: bool) -> Self {
+        self.legacy_api = enabled;
+        self
+    }
+
+    /// Also serve Prometheus metrics at `/metrics` of the scope. Left out by default, as the
+    /// application usually exposes its own.
+    #[must_use]
+    pub fn metrics(mut self, enabled: bool) -> Self {
+        self.metrics = enabled;
+        self
+    }
+
+    /// Also serve `/health/live` and `/health/ready` probes of the scope. Left out by default,
+    /// as the application usually has its own.
+    #[must_use]
+    pub fn health_probes(mut self, enabled: bool) -> Self {
+        self.health_probes = enabled;
+        self
+    }
+
+    /// Validate configuration and create [`Avanguard`]. Fails if store is not set
+    /// or the audit log can't be opened.
+    pub fn build(self) -> Result<Avanguard, ConfigError> {
+        let pool = self
+            .store
+            .ok_or_else(|| ConfigError::Invalid("store is not set".into()))?;
+        self.config.validate()?;
+        self.config.validate_secrets()?;
+        let mut state = AppState::new(self.config, pool)?;
+        state.clients = self.clients;
+        state.hooks = self.hooks;
+        state.claims_providers.extend(self.claims_providers);
+        Ok(Avanguard {
+            state: web::Data::new(state),
+            legacy_api: self.legacy_api,
+            metrics: self.metrics,
+            health_probes: self.health_probes,
+        })
+    }
+}
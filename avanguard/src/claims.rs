@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use openidconnect::AdditionalClaims;
+use serde_json::{Map, Value};
+use sqlx::{query, query_scalar, PgExecutor};
+use thiserror::Error;
+use tracing::instrument;
+
+use crate::db::{DbPool, Group, Wallet};
+
+/// Scope requesting groups of the wallet in the groups claim.
+pub const GROUPS_SCOPE: &str = "groups";
+
+/// Claims set by Avanguard, which providers can't override.
+pub(crate) const RESERVED_CLAIMS: [&str; 10] = [
+    "iss",
+    "sub",
+    "aud",
+    "exp",
+    "iat",
+    "nbf",
+    "jti",
+    "nonce",
+    "auth_time",
+    "azp",
+];
+
+#[derive(Debug, Error)]
+#[error("failed to get claims: {0}")]
+pub struct ClaimsError(pub String);
+
+impl From<sqlx::Error> for ClaimsError {
+    fn from(err: sqlx::Error) -> Self {
+        Self(err.to_string())
+    }
+}
+
+/// Token being issued, passed to [`ClaimsProvider`].
+pub struct ClaimsRequest<'a> {
+    pub wallet: &'a Wallet,
+    /// Client the token is issued to.
+    pub client_id: &'a str,
+    /// Scopes requested at login, also when the token is refreshed.
+    pub scopes: &'a [&'a str],
+}
+
+/// Source of custom claims added to issued tokens, e.g. roles or tenant IDs.
+#[async_trait]
+pub trait ClaimsProvider: Send + Sync {
+    /// Claims of the token. Fails login or refresh on error.
+    async fn claims(&self, request: &ClaimsRequest<'_>) -> Result<Map<String, Value>, ClaimsError>;
+}
+
+/// Custom claims serialized next to standard claims of the id token.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ExtraClaims(pub Map<String, Value>);
+
+impl AdditionalClaims for ExtraClaims {}
+
+/// Merge claims of `providers` in order, so later providers override earlier ones.
+/// Reserved claims are dropped.
+pub(crate) async fn collect_claims(
+    providers: &[Arc<dyn ClaimsProvider>],
+    request: &ClaimsRequest<'_>,
+) -> Result<ExtraClaims, ClaimsError> {
+    let mut claims = Map::new();
+    for provider in providers {
+        for (name, value) in provider.claims(request).await? {
+            if RESERVED_CLAIMS.contains(&name.as_str()) {
+                log::warn!(
+                    "Ignoring reserved claim {name} of wallet {}",
+                    request.wallet.address
+                );
+            } else {
+                claims.insert(name, value);
+            }
+        }
+    }
+    Ok(ExtraClaims(claims))
+}
+
+/// Claims stored per wallet address in `static_claims` table, regardless of client and scopes.
+/// Addresses don't need to be registered yet.
+pub struct StaticClaims {
+    pool: DbPool,
+}
+
+impl StaticClaims {
+    #[must_use]
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Claims stored for `address`.
+    #[instrument(skip_all)]
+    pub async fn get<'e, E: PgExecutor<'e>>(
+        executor: E,
+        address: &str,
+    ) -> Result<Option<Map<String, Value>>, sqlx::Error> {
+        let claims: Option<Value> = query_scalar!(
+            "SELECT claims FROM static_claims WHERE address = $1",
+            address.to_lowercase()
+        )
+        .fetch_optional(executor)
+        .await?;
+        Ok(claims.and_then(|claims| match claims {
+            Value::Object(claims) => Some(claims),
+            _ => None,
+        }))
+    }
+
+    /// Replace claims stored for `address`.
+    #[instrument(skip_all)]
+    pub async fn set<'e, E: PgExecutor<'e>>(
+        executor: E,
+        address: &str,
+        claims: &Map<String, Value>,
+    ) -> Result<(), sqlx::Error> {
+        query!(
+            "INSERT INTO static_claims (address, claims, updated_at) VALUES ($1, $2, $3) \
+            ON CONFLICT (address) DO UPDATE SET claims = $2, updated_at = $3",
+            address.to_lowercase(),
+            Value::Object(claims.clone()),
+            Utc::now().naive_utc()
+        )
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    /// Remove claims stored for `address`. Returns whether there were any.
+    #[instrument(skip_all)]
+    pub async fn remove<'e, E: PgExecutor<'e>>(
+        executor: E,
+        address: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = query!(
+            "DELETE FROM static_claims WHERE address = $1",
+            address.to_lowercase()
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl ClaimsProvider for StaticClaims {
+    async fn claims(&self, request: &ClaimsRequest<'_>) -> Result<Map<String, Value>, ClaimsError> {
+        Ok(Self::get(&self.pool, &request.wallet.address)
+            .await?
+            .unwrap_or_default())
+    }
+}
+
+/// Names of groups the wallet belongs to, in claim `claim` of tokens requested
+/// with [`GROUPS_SCOPE`].
+pub struct GroupsClaims {
+    pool: DbPool,
+    claim: String,
+}
+
+impl GroupsClaims {
+    #[must_use]
+    pub fn new(pool: DbPool, claim: String) -> Self {
+        Self { pool, claim }
+    }
+}
+
+#[async_trait]
+impl ClaimsProvider for GroupsClaims {
+    async fn claims(&self, request: &ClaimsRequest<'_>) -> Result<Map<String, Value>, ClaimsError> {
+        let mut claims = Map::new();
+        if !request.scopes.contains(&GROUPS_SCOPE) {
+            return Ok(claims);
+        }
+        let groups = match request.wallet.id {
+            Some(wallet_id) => Group::names_by_wallet(&self.pool, wallet_id).await?,
+            None => Vec::new(),
+        };
+        claims.insert(self.claim.clone(), groups.into());
+        Ok(claims)
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration as StdDuration;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use sqlx::query_scalar;
+
+use crate::{
+    db::{DbPool, LoginEvent, RefreshToken, Wallet},
+    events,
+    lockout::LockoutPolicy,
+    ratelimit::RateLimiter,
+    webhook, Config,
+};
+
+/// Postgres advisory lock key held while cleanup is running, so only one replica runs it.
+const CLEANUP_LOCK_KEY: i64 = 0x6176_616e_6775_6172;
+
+/// Number of records deleted by a single cleanup run.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CleanupReport {
+    pub expired_tokens: u64,
+    pub revoked_tokens: u64,
+    pub unverified_wallets: u64,
+    pub login_events: u64,
+    pub webhook_deliveries: u64,
+    pub published_events: u64,
+    pub rate_limits: u64,
+    pub failed_login_ips: u64,
+}
+
+fn cutoff(retention: u32) -> NaiveDateTime {
+    (Utc::now() - Duration::seconds(retention.into())).naive_utc()
+}
+
+/// Repeat batched delete until a batch deletes less than `limit` rows.
+async fn delete_in_batches<F, Fut>(limit: i64, delete: F) -> Result<u64, sqlx::Error>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<u64, sqlx::Error>>,
+{
+    let mut total = 0;
+    loop {
+        let deleted = delete().await?;
+        total += deleted;
+        if deleted < limit as u64 {
+            return Ok(total);
+        }
+    }
+}
+
+/// Purge expired and revoked refresh tokens, unverified wallets, old login events,
+/// webhook deliveries and published events.
+/// Returns `None` if cleanup is already being run by another instance.
+pub async fn run_cleanup(
+    pool: &DbPool,
+    config: &Config,
+) -> Result<Option<CleanupReport>, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    let locked = query_scalar!(
+        "SELECT pg_try_advisory_lock($1) \"locked!\"",
+        CLEANUP_LOCK_KEY
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if !locked {
+        log::debug!("Cleanup is already running on another instance, skipping");
+        return Ok(None);
+    }
+
+    let limit = i64::from(config.cleanup_batch_size.max(1));
+    let result = async {
+        let expired_tokens = delete_in_batches(limit, || {
+            RefreshToken::delete_expired(pool, cutoff(config.expired_token_retention), limit)
+        })
+        .await?;
+        let revoked_tokens = delete_in_batches(limit, || {
+            RefreshToken::delete_revoked(pool, cutoff(config.revoked_token_retention), limit)
+        })
+        .await?;
+        let unverified_wallets = delete_in_batches(limit, || {
+            Wallet::delete_unverified(pool, cutoff(config.unverified_wallet_retention), limit)
+        })
+        .await?;
+        let login_events = delete_in_batches(limit, || {
+            LoginEvent::delete_old(pool, cutoff(config.login_event_retention), limit)
+        })
+        .await?;
+        let webhook_deliveries = delete_in_batches(limit, || {
+            webhook::delete_finished(pool, cutoff(config.webhook_delivery_retention), limit)
+        })
+        .await?;
+        let published_events = delete_in_batches(limit, || {
+            events::delete_published(pool, cutoff(config.event_retention), limit)
+        })
+        .await?;
+        let rate_limits =
+            RateLimiter::delete_expired(pool, cutoff(config.rate_limit_window)).await?;
+        let failed_login_ips =
+            LockoutPolicy::delete_stale_ips(pool, cutoff(config.lockout_max_duration)).await?;
+        Ok(CleanupReport {
+            expired_tokens,
+            revoked_tokens,
+            unverified_wallets,
+            login_events,
+            webhook_deliveries,
+            published_events,
+            rate_limits,
+            failed_login_ips,
+        })
+    }
+    .await;
+
+    let unlocked = query_scalar!(
+        "SELECT pg_advisory_unlock($1) \"unlocked!\"",
+        CLEANUP_LOCK_KEY
+    )
+    .fetch_one(&mut *conn)
+    .await;
+    if unlocked.is_err() {
+        // Closing the connection releases the lock instead
+        drop(conn.detach());
+    }
+    result.map(Some)
+}
+
+/// Run cleanup periodically, every `cleanup_interval` seconds.
+pub async fn run_cleanup_task(pool: DbPool, config: Config) {
+    if config.cleanup_interval == 0 {
+        log::info!("Database cleanup disabled");
+        return;
+    }
+    let mut interval =
+        actix_web::rt::time::interval(StdDuration::from_secs(config.cleanup_interval.into()));
+    loop {
+        interval.tick().await;
+        match run_cleanup(&pool, &config).await {
+            Ok(Some(report)) => log::info!(
+                "Database cleanup deleted {} expired tokens, {} used or blacklisted tokens, \
+                {} unverified wallets, {} login events, {} webhook deliveries, {} published events, {} rate limit counters and {} failed login IP counters",
+                report.expired_tokens,
+                report.revoked_tokens,
+                report.unverified_wallets,
+                report.login_events,
+                report.webhook_deliveries,
+                report.published_events,
+                report.rate_limits,
+                report.failed_login_ips,
+            ),
+            Ok(None) => (),
+            Err(err) => log::error!("Database cleanup failed: {err}"),
+        }
+    }
+}
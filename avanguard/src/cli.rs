@@ -0,0 +1,801 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use clap::{Subcommand, ValueEnum};
+use serde::Serialize;
+use sqlx::migrate::MigrateError;
+use thiserror::Error;
+
+use crate::{
+    audit::{AuditEvent, AuditLog},
+    claims::StaticClaims,
+    cors::OriginPattern,
+    db::{
+        is_unique_violation, migrate, migration_status, rollback, Client, DbPool, Group,
+        RefreshToken, Session, Wallet,
+    },
+    events::{emit, AuthEvent},
+    random::gen_hex,
+    Config,
+};
+
+/// Number of random bytes in generated keys.
+const KEY_BYTES: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+    #[error(transparent)]
+    Migrate(#[from] MigrateError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// Administrative commands, the HTTP server is started if none is given.
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Manage database schema
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Manage wallets
+    #[command(subcommand)]
+    Wallet(WalletCommand),
+    /// Manage refresh tokens
+    #[command(subcommand)]
+    Token(TokenCommand),
+    /// Manage registered OIDC clients
+    #[command(subcommand)]
+    Client(ClientCommand),
+    /// Manage claims added to tokens of a wallet with --static-claims
+    #[command(subcommand)]
+    Claims(ClaimsCommand),
+    /// Manage groups of wallets, added to tokens requested with `groups` scope
+    #[command(subcommand)]
+    Group(GroupCommand),
+    /// Generate and rotate secrets
+    #[command(subcommand)]
+    Keys(KeysCommand),
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum DbCommand {
+    /// Apply pending migrations
+    Migrate,
+    /// Revert latest applied migrations
+    Rollback {
+        /// Number of migrations to revert
+        #[arg(default_value_t = 1)]
+        steps: usize,
+    },
+    /// List migrations and whether they are applied
+    Status,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum WalletCommand {
+    /// List all wallets
+    List,
+    /// Show wallet and its active sessions
+    Show { address: String },
+    /// Disable login with the wallet and revoke its refresh tokens
+    Disable { address: String },
+    /// Enable login with disabled wallet
+    Enable { address: String },
+    /// Delete wallet together with its refresh tokens and login history
+    Delete { address: String },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum TokenCommand {
+    /// Revoke all refresh tokens of a wallet
+    Revoke {
+        /// Wallet address
+        #[arg(long)]
+        wallet: String,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ClientCommand {
+    /// Register client with random secret
+    Add {
+        client_id: String,
+        /// Human-readable name, defaults to client id
+        #[arg(long)]
+        name: Option<String>,
+        /// Origin allowed to make CORS requests, e.g. https://app.example.com, can be repeated
+        #[arg(long = "origin")]
+        origins: Vec<String>,
+    },
+    /// List registered clients
+    List,
+    /// Replace origins allowed to make CORS requests, running servers apply them within a minute
+    SetOrigins {
+        client_id: String,
+        /// Allowed origins, none to allow only configured client_origin_url
+        origins: Vec<String>,
+    },
+    /// Replace client secret with a new random one, invalidating tokens signed with the old one
+    RotateSecret { client_id: String },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ClaimsCommand {
+    /// Show claims of a wallet address
+    Show { address: String },
+    /// Replace claims of a wallet address with JSON object, e.g. '{"role": "admin"}'
+    Set { address: String, claims: String },
+    /// Remove claims of a wallet address
+    Unset { address: String },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum GroupCommand {
+    /// Create group
+    Add { name: String },
+    /// List groups
+    List,
+    /// Delete group, removing its wallets from it
+    Delete { name: String },
+    /// List wallets in a group
+    Members { name: String },
+    /// Add wallet to a group
+    Assign { name: String, address: String },
+    /// Remove wallet from a group
+    Unassign { name: String, address: String },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum KeysCommand {
+    /// Print random key suitable as client secret, refresh token secret or admin token
+    Generate,
+    /// Write new random key to the file the secret is read from, running servers pick it up
+    /// on next secret refresh
+    Rotate {
+        #[arg(value_enum)]
+        secret: SecretName,
+    },
+}
+
+/// Secrets which can be rotated with `keys rotate`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SecretName {
+    ClientSecret,
+    RefreshTokenSecret,
+    AdminToken,
+}
+
+/// Output format of commands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+/// Writes command results in the configured format.
+struct Output<'a, W: Write> {
+    out: &'a mut W,
+    format: OutputFormat,
+}
+
+impl<W: Write> Output<'_, W> {
+    /// Write `items` as JSON array or as table with `columns`, values taken from `row`.
+    fn list<T: Serialize>(
+        &mut self,
+        items: &[T],
+        columns: &[&str],
+        row: impl Fn(&T) -> Vec<String>,
+    ) -> Result<(), CliError> {
+        match self.format {
+            OutputFormat::Json => self.json(&items),
+            OutputFormat::Table => {
+                let rows: Vec<_> = items.iter().map(row).collect();
+                let mut widths: Vec<_> = columns.iter().map(|column| column.len()).collect();
+                for row in &rows {
+                    for (width, value) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(value.len());
+                    }
+                }
+                let header = columns.iter().map(ToString::to_string).collect();
+                for row in std::iter::once(header).chain(rows) {
+                    let line = row
+                        .iter()
+                        .zip(&widths)
+                        .map(|(value, width)| format!("{value:<width$}"))
+                        .collect::<Vec<_>>()
+                        .join("  ");
+                    writeln!(self.out, "{}", line.trim_end())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Write `value` as JSON, or `message` in table format.
+    fn message<T: Serialize>(&mut self, value: &T, message: &str) -> Result<(), CliError> {
+        match self.format {
+            OutputFormat::Json => self.json(value),
+            OutputFormat::Table => Ok(writeln!(self.out, "{message}")?),
+        }
+    }
+
+    fn json<T: Serialize>(&mut self, value: &T) -> Result<(), CliError> {
+        serde_json::to_writer_pretty(&mut *self.out, value)?;
+        Ok(writeln!(self.out)?)
+    }
+}
+
+fn format_time(time: Option<chrono::NaiveDateTime>) -> String {
+    time.map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// Run `command`, writing its output to `out`.
+pub async fn run_command(
+    command: &Command,
+    config: &Config,
+    pool: &DbPool,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let mut output = Output {
+        out,
+        format: config.output,
+    };
+    match command {
+        Command::Db(command) => run_db_command(command, pool, &mut output).await,
+        Command::Wallet(command) => run_wallet_command(command, config, pool, &mut output).await,
+        Command::Token(command) => run_token_command(command, config, pool, &mut output).await,
+        Command::Client(command) => run_client_command(command, config, pool, &mut output).await,
+        Command::Claims(command) => run_claims_command(command, config, pool, &mut output).await,
+        Command::Group(command) => run_group_command(command, config, pool, &mut output).await,
+        Command::Keys(command) => run_keys_command(command, config, &mut output),
+    }
+}
+
+async fn run_db_command(
+    command: &DbCommand,
+    pool: &DbPool,
+    output: &mut Output<'_, impl Write>,
+) -> Result<(), CliError> {
+    match command {
+        DbCommand::Migrate => {
+            let pending: Vec<_> = migration_status(pool)
+                .await?
+                .into_iter()
+                .filter(|migration| !migration.applied)
+                .collect();
+            migrate(pool).await?;
+            if output.format == OutputFormat::Json {
+                return output.json(&pending);
+            }
+            for migration in &pending {
+                writeln!(
+                    output.out,
+                    "Applied {} {}",
+                    migration.version, migration.description
+                )?;
+            }
+            if pending.is_empty() {
+                writeln!(output.out, "Database schema is up to date")?;
+            }
+            Ok(())
+        }
+        DbCommand::Rollback { steps } => {
+            let reverted = rollback(pool, *steps).await?;
+            if output.format == OutputFormat::Json {
+                return output.json(&reverted);
+            }
+            for version in &reverted {
+                writeln!(output.out, "Reverted {version}")?;
+            }
+            if reverted.is_empty() {
+                writeln!(output.out, "No migrations to revert")?;
+            }
+            Ok(())
+        }
+        DbCommand::Status => {
+            let status = migration_status(pool).await?;
+            output.list(
+                &status,
+                &["VERSION", "STATUS", "DESCRIPTION"],
+                |migration| {
+                    let status = if migration.applied {
+                        "applied"
+                    } else {
+                        "pending"
+                    };
+                    vec![
+                        migration.version.to_string(),
+                        status.into(),
+                        migration.description.clone(),
+                    ]
+                },
+            )
+        }
+    }
+}
+
+async fn find_wallet(pool: &DbPool, address: &str) -> Result<Wallet, CliError> {
+    Wallet::find_by_address(pool, &address.to_lowercase())
+        .await?
+        .ok_or_else(|| CliError::NotFound(format!("wallet {address}")))
+}
+
+fn wallet_row(wallet: &Wallet) -> Vec<String> {
+    vec![
+        wallet.address.clone(),
+        format_time(Some(wallet.creation_timestamp)),
+        format_time(wallet.validation_timestamp),
+        wallet.failed_login_attempts.to_string(),
+        format_time(wallet.locked_until),
+        format_time(wallet.disabled_at),
+    ]
+}
+
+const WALLET_COLUMNS: [&str; 6] = [
+    "ADDRESS",
+    "CREATED",
+    "VALIDATED",
+    "FAILED LOGINS",
+    "LOCKED UNTIL",
+    "DISABLED",
+];
+
+/// Revoke all refresh tokens of `wallet` and record it. Returns number of revoked tokens.
+async fn revoke_tokens(config: &Config, pool: &DbPool, wallet: &Wallet) -> Result<u64, CliError> {
+    let Some(wallet_id) = wallet.id else {
+        return Ok(0);
+    };
+    let mut transaction = pool.begin().await?;
+    let count = RefreshToken::blacklist_wallet(&mut *transaction, wallet_id).await?;
+    if count > 0 {
+        let event = AuthEvent::SessionRevoked {
+            address: wallet.address.clone(),
+            session_id: None,
+            count,
+        };
+        emit(&mut transaction, config, &event).await?;
+    }
+    transaction.commit().await?;
+    Ok(count)
+}
+
+/// Record administrative action in audit log.
+fn audit(config: &Config, wallet: Option<&str>, event: AuditEvent) -> Result<(), CliError> {
+    AuditLog::open(config.audit_log.as_ref())?.log_command(config, wallet, event);
+    Ok(())
+}
+
+fn admin_action(action: &str) -> AuditEvent {
+    AuditEvent::AdminAction {
+        action: action.into(),
+    }
+}
+
+async fn run_wallet_command(
+    command: &WalletCommand,
+    config: &Config,
+    pool: &DbPool,
+    output: &mut Output<'_, impl Write>,
+) -> Result<(), CliError> {
+    match command {
+        WalletCommand::List => {
+            let wallets = Wallet::all(pool).await?;
+            output.list(&wallets, &WALLET_COLUMNS, wallet_row)
+        }
+        WalletCommand::Show { address } => {
+            let wallet = find_wallet(pool, address).await?;
+            let sessions = match wallet.id {
+                Some(id) => Session::find_by_wallet(pool, id).await?,
+                None => Vec::new(),
+            };
+            if output.format == OutputFormat::Json {
+                #[derive(Serialize)]
+                struct WalletDetails<'a> {
+                    #[serde(flatten)]
+                    wallet: &'a Wallet,
+                    sessions: &'a [Session],
+                }
+                return output.json(&WalletDetails {
+                    wallet: &wallet,
+                    sessions: &sessions,
+                });
+            }
+            output.list(&[&wallet], &WALLET_COLUMNS, |wallet| wallet_row(wallet))?;
+            writeln!(output.out)?;
+            output.list(
+                &sessions,
+                &["SESSION", "LOGGED IN", "CLIENT", "IP", "EXPIRES"],
+                |session| {
+                    vec![
+                        session.login_event_id.to_string(),
+                        format_time(Some(session.logged_in_at)),
+                        session.client_id.clone(),
+                        session.ip.clone().unwrap_or_default(),
+                        format_time(Some(session.expires_at)),
+                    ]
+                },
+            )
+        }
+        WalletCommand::Disable { address } => {
+            let mut wallet = find_wallet(pool, address).await?;
+            wallet.set_disabled(pool, true).await?;
+            let count = revoke_tokens(config, pool, &wallet).await?;
+            audit(
+                config,
+                Some(&wallet.address),
+                admin_action("disable_wallet"),
+            )?;
+            output.message(
+                &wallet,
+                &format!(
+                    "Disabled wallet {}, revoked {count} refresh tokens",
+                    wallet.address
+                ),
+            )
+        }
+        WalletCommand::Enable { address } => {
+            let mut wallet = find_wallet(pool, address).await?;
+            wallet.set_disabled(pool, false).await?;
+            audit(config, Some(&wallet.address), admin_action("enable_wallet"))?;
+            output.message(&wallet, &format!("Enabled wallet {}", wallet.address))
+        }
+        WalletCommand::Delete { address } => {
+            let wallet = find_wallet(pool, address).await?;
+            let address = wallet.address.clone();
+            let mut transaction = pool.begin().await?;
+            if let Some(id) = wallet.id {
+                RefreshToken::delete_by_wallet(&mut *transaction, id).await?;
+            }
+            wallet.delete(&mut *transaction).await?;
+            let event = AuthEvent::WalletDeleted {
+                address: address.clone(),
+            };
+            emit(&mut transaction, config, &event).await?;
+            transaction.commit().await?;
+            audit(config, Some(&address), admin_action("delete_wallet"))?;
+            output.message(
+                &serde_json::json!({ "address": address }),
+                &format!("Deleted wallet {address}"),
+            )
+        }
+    }
+}
+
+async fn run_token_command(
+    command: &TokenCommand,
+    config: &Config,
+    pool: &DbPool,
+    output: &mut Output<'_, impl Write>,
+) -> Result<(), CliError> {
+    match command {
+        TokenCommand::Revoke { wallet } => {
+            let wallet = find_wallet(pool, wallet).await?;
+            let count = revoke_tokens(config, pool, &wallet).await?;
+            audit(
+                config,
+                Some(&wallet.address),
+                AuditEvent::TokenRevoked { count },
+            )?;
+            output.message(
+                &serde_json::json!({ "address": wallet.address, "revoked": count }),
+                &format!(
+                    "Revoked {count} refresh tokens of wallet {}",
+                    wallet.address
+                ),
+            )
+        }
+    }
+}
+
+/// Client with its secret, shown only when it's created or rotated.
+#[derive(Serialize)]
+struct ClientSecret<'a> {
+    #[serde(flatten)]
+    client: &'a Client,
+    secret: &'a str,
+}
+
+/// Validate allowed origins of a client, returns them normalized.
+fn parse_origins(origins: &[String]) -> Result<Vec<String>, CliError> {
+    origins
+        .iter()
+        .map(|origin| match origin.parse::<OriginPattern>() {
+            Ok(OriginPattern::Any) => Err(CliError::Invalid(
+                "client origins must not be *, set client_origin_url instead".into(),
+            )),
+            Ok(pattern) => Ok(pattern.to_string()),
+            Err(err) => Err(CliError::Invalid(err)),
+        })
+        .collect()
+}
+
+async fn run_client_command(
+    command: &ClientCommand,
+    config: &Config,
+    pool: &DbPool,
+    output: &mut Output<'_, impl Write>,
+) -> Result<(), CliError> {
+    match command {
+        ClientCommand::Add {
+            client_id,
+            name,
+            origins,
+        } => {
+            if *client_id == config.client_id
+                || Client::find_by_client_id(pool, client_id).await?.is_some()
+            {
+                return Err(CliError::Invalid(format!(
+                    "client {client_id} already exists"
+                )));
+            }
+            let name = name.clone().unwrap_or_else(|| client_id.clone());
+            let mut client = Client::new(client_id.clone(), name);
+            client.allowed_origins = parse_origins(origins)?;
+            client.save(pool).await?;
+            audit(config, None, admin_action("add_client"))?;
+            output.list(
+                &[ClientSecret {
+                    client: &client,
+                    secret: &client.secret,
+                }],
+                &["CLIENT ID", "NAME", "SECRET"],
+                |client| {
+                    vec![
+                        client.client.client_id.clone(),
+                        client.client.name.clone(),
+                        client.secret.into(),
+                    ]
+                },
+            )
+        }
+        ClientCommand::List => {
+            let clients = Client::all(pool).await?;
+            output.list(
+                &clients,
+                &["CLIENT ID", "NAME", "CREATED", "ORIGINS"],
+                |client| {
+                    vec![
+                        client.client_id.clone(),
+                        client.name.clone(),
+                        format_time(Some(client.created_at)),
+                        client.allowed_origins.join(","),
+                    ]
+                },
+            )
+        }
+        ClientCommand::SetOrigins { client_id, origins } => {
+            let Some(mut client) = Client::find_by_client_id(pool, client_id).await? else {
+                return Err(CliError::NotFound(format!("client {client_id}")));
+            };
+            client.allowed_origins = parse_origins(origins)?;
+            client.save(pool).await?;
+            audit(config, None, admin_action("set_client_origins"))?;
+            output.message(
+                &client,
+                &format!(
+                    "Allowed origins of client {client_id}: {}",
+                    client.allowed_origins.join(",")
+                ),
+            )
+        }
+        ClientCommand::RotateSecret { client_id } => {
+            let Some(mut client) = Client::find_by_client_id(pool, client_id).await? else {
+                if *client_id == config.client_id {
+                    return Err(CliError::Invalid(format!(
+                        "client {client_id} is configured with client_secret, \
+                        rotate it with `keys rotate client-secret`"
+                    )));
+                }
+                return Err(CliError::NotFound(format!("client {client_id}")));
+            };
+            client.rotate_secret();
+            client.save(pool).await?;
+            audit(config, None, admin_action("rotate_client_secret"))?;
+            output.list(
+                &[ClientSecret {
+                    client: &client,
+                    secret: &client.secret,
+                }],
+                &["CLIENT ID", "SECRET"],
+                |client| vec![client.client.client_id.clone(), client.secret.into()],
+            )
+        }
+    }
+}
+
+async fn run_claims_command(
+    command: &ClaimsCommand,
+    config: &Config,
+    pool: &DbPool,
+    output: &mut Output<'_, impl Write>,
+) -> Result<(), CliError> {
+    match command {
+        ClaimsCommand::Show { address } => {
+            let claims = StaticClaims::get(pool, address)
+                .await?
+                .ok_or_else(|| CliError::NotFound(format!("claims of {address}")))?;
+            output.message(&claims, &serde_json::to_string_pretty(&claims)?)
+        }
+        ClaimsCommand::Set { address, claims } => {
+            let serde_json::Value::Object(claims) = serde_json::from_str(claims)? else {
+                return Err(CliError::Invalid("claims must be a JSON object".into()));
+            };
+            StaticClaims::set(pool, address, &claims).await?;
+            audit(config, Some(address), admin_action("set_claims"))?;
+            output.message(&claims, &format!("Set claims of {address}"))
+        }
+        ClaimsCommand::Unset { address } => {
+            if !StaticClaims::remove(pool, address).await? {
+                return Err(CliError::NotFound(format!("claims of {address}")));
+            }
+            audit(config, Some(address), admin_action("unset_claims"))?;
+            output.message(
+                &serde_json::json!({ "address": address }),
+                &format!("Removed claims of {address}"),
+            )
+        }
+    }
+}
+
+async fn find_group(pool: &DbPool, name: &str) -> Result<Group, CliError> {
+    Group::find_by_name(pool, name)
+        .await?
+        .ok_or_else(|| CliError::NotFound(format!("group {name}")))
+}
+
+/// Id of wallet `address`, which must exist.
+async fn find_wallet_id(pool: &DbPool, address: &str) -> Result<i64, CliError> {
+    find_wallet(pool, address)
+        .await?
+        .id
+        .ok_or_else(|| CliError::NotFound(format!("wallet {address}")))
+}
+
+async fn run_group_command(
+    command: &GroupCommand,
+    config: &Config,
+    pool: &DbPool,
+    output: &mut Output<'_, impl Write>,
+) -> Result<(), CliError> {
+    match command {
+        GroupCommand::Add { name } => {
+            if !Group::is_valid_name(name) {
+                return Err(CliError::Invalid(format!("invalid group name {name:?}")));
+            }
+            let mut group = Group::new(name.clone());
+            group.save(pool).await.map_err(|err| match err {
+                err if is_unique_violation(&err) => {
+                    CliError::Invalid(format!("group {name} already exists"))
+                }
+                err => err.into(),
+            })?;
+            audit(config, None, admin_action(&format!("create_group {name}")))?;
+            output.message(&group, &format!("Created group {name}"))
+        }
+        GroupCommand::List => {
+            let groups = Group::all(pool).await?;
+            output.list(&groups, &["NAME", "CREATED"], |group| {
+                vec![group.name.clone(), format_time(Some(group.created_at))]
+            })
+        }
+        GroupCommand::Delete { name } => {
+            find_group(pool, name).await?.delete(pool).await?;
+            audit(config, None, admin_action(&format!("delete_group {name}")))?;
+            output.message(
+                &serde_json::json!({ "name": name }),
+                &format!("Deleted group {name}"),
+            )
+        }
+        GroupCommand::Members { name } => {
+            let members = find_group(pool, name).await?.members(pool).await?;
+            output.list(&members, &["ADDRESS"], |address| vec![address.clone()])
+        }
+        GroupCommand::Assign { name, address } => {
+            let group = find_group(pool, name).await?;
+            let wallet_id = find_wallet_id(pool, address).await?;
+            group.add_wallet(pool, wallet_id).await?;
+            let address = address.to_lowercase();
+            audit(
+                config,
+                Some(&address),
+                admin_action(&format!("add_to_group {name}")),
+            )?;
+            output.message(
+                &serde_json::json!({ "name": name, "address": address }),
+                &format!("Added wallet {address} to group {name}"),
+            )
+        }
+        GroupCommand::Unassign { name, address } => {
+            let group = find_group(pool, name).await?;
+            let wallet_id = find_wallet_id(pool, address).await?;
+            let address = address.to_lowercase();
+            if !group.remove_wallet(pool, wallet_id).await? {
+                return Err(CliError::NotFound(format!(
+                    "wallet {address} in group {name}"
+                )));
+            }
+            audit(
+                config,
+                Some(&address),
+                admin_action(&format!("remove_from_group {name}")),
+            )?;
+            output.message(
+                &serde_json::json!({ "name": name, "address": address }),
+                &format!("Removed wallet {address} from group {name}"),
+            )
+        }
+    }
+}
+
+/// Replace file content without readers seeing partially written key.
+fn write_key(path: &Path, key: &str) -> io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let mut file = fs::OpenOptions::new();
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut file, 0o600);
+    let mut file = file
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temp_path)?;
+    writeln!(file, "{key}")?;
+    file.sync_all()?;
+    fs::rename(temp_path, path)
+}
+
+fn run_keys_command(
+    command: &KeysCommand,
+    config: &Config,
+    output: &mut Output<'_, impl Write>,
+) -> Result<(), CliError> {
+    match command {
+        KeysCommand::Generate => {
+            let key = gen_hex(KEY_BYTES);
+            output.message(&serde_json::json!({ "key": key }), &key)
+        }
+        KeysCommand::Rotate { secret } => {
+            let (name, path) = match secret {
+                SecretName::ClientSecret => ("client_secret", &config.client_secret_file),
+                SecretName::RefreshTokenSecret => {
+                    ("refresh_token_secret", &config.refresh_token_secret_file)
+                }
+                SecretName::AdminToken => ("admin_token", &config.admin_token_file),
+            };
+            let Some(path) = path else {
+                return Err(CliError::Invalid(format!(
+                    "{name} is not read from a file, set {name}_file or rotate it where it's configured"
+                )));
+            };
+            write_key(path, &gen_hex(KEY_BYTES))?;
+            audit(config, None, admin_action(&format!("rotate_{name}")))?;
+            let mut applied = match config.secret_refresh_interval {
+                0 => "restart servers to apply it".to_string(),
+                interval => format!("servers apply it within {interval} seconds"),
+            };
+            if matches!(secret, SecretName::RefreshTokenSecret) {
+                applied.push_str(if config.secret_refresh_interval == 0 {
+                    "; WARNING: restart invalidates all refresh tokens, logging out every user"
+                } else {
+                    "; refresh tokens issued before stay valid until they expire or servers restart"
+                });
+            }
+            output.message(
+                &serde_json::json!({ "secret": name, "file": path }),
+                &format!("Rotated {name} in {}, {applied}", path.display()),
+            )
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::{fmt, str::FromStr, time::Duration as StdDuration};
+
+use actix_cors::Cors;
+use actix_web::{http::header, web};
+use openidconnect::url::{Origin, Url};
+use serde::{Serialize, Serializer};
+
+use crate::{state::AppState, telemetry::REQUEST_ID_HEADER};
+
+/// Host label substituted for wildcard to parse patterns as URLs.
+const WILDCARD_LABEL: &str = "wildcard";
+
+/// Interval in which allowed origins of registered clients are loaded.
+const CLIENT_ORIGINS_INTERVAL: StdDuration = StdDuration::from_secs(60);
+
+/// Origin allowed to make CORS requests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OriginPattern {
+    /// Any origin, `*`.
+    Any,
+    /// Single origin, e.g. `https://app.example.com`.
+    Exact(String),
+    /// Subdomains of a domain with given scheme and port, e.g. `https://*.example.com`.
+    Subdomain {
+        scheme: String,
+        /// Part of the origin after subdomain, e.g. `.example.com` or `.example.com:8443`.
+        suffix: String,
+    },
+}
+
+impl OriginPattern {
+    /// Whether `origin` header value matches the pattern.
+    #[must_use]
+    pub fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Exact(allowed) => allowed.eq_ignore_ascii_case(origin),
+            Self::Subdomain { scheme, suffix } => {
+                let origin = origin.to_ascii_lowercase();
+                let Some(host) = origin
+                    .strip_prefix(scheme.as_str())
+                    .and_then(|rest| rest.strip_prefix("://"))
+                    .and_then(|rest| rest.strip_suffix(suffix.as_str()))
+                else {
+                    return false;
+                };
+                !host.is_empty()
+                    && host
+                        .split('.')
+                        .all(|label| !label.is_empty() && label.bytes().all(is_label_byte))
+            }
+        }
+    }
+}
+
+fn is_label_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'-'
+}
+
+/// Serialize origin of `url` as sent in `Origin` header.
+fn serialize_origin(url: &Url) -> Result<String, String> {
+    match url.origin() {
+        origin @ Origin::Tuple(..) if url.path() == "/" && url.query().is_none() => {
+            Ok(origin.ascii_serialization())
+        }
+        Origin::Tuple(..) => Err(format!("origin {url} must not contain path")),
+        Origin::Opaque(_) => Err(format!("{url} is not a valid origin")),
+    }
+}
+
+impl FromStr for OriginPattern {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        if pattern == "*" {
+            return Ok(Self::Any);
+        }
+        let invalid =
+            |_| format!("invalid origin {pattern}, expected <scheme>://[*.]<host>[:<port>]");
+        match pattern.split_once("://*.") {
+            Some((scheme, rest)) => {
+                let url =
+                    Url::parse(&format!("{scheme}://{WILDCARD_LABEL}.{rest}")).map_err(invalid)?;
+                let origin = serialize_origin(&url)?;
+                let suffix = origin
+                    .split_once(WILDCARD_LABEL)
+                    .map(|(_, suffix)| suffix.to_string())
+                    .ok_or_else(|| format!("invalid origin {pattern}"))?;
+                Ok(Self::Subdomain {
+                    scheme: url.scheme().into(),
+                    suffix,
+                })
+            }
+            None => {
+                let url = Url::parse(pattern).map_err(invalid)?;
+                serialize_origin(&url).map(Self::Exact)
+            }
+        }
+    }
+}
+
+impl fmt::Display for OriginPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => write!(f, "*"),
+            Self::Exact(origin) => write!(f, "{origin}"),
+            Self::Subdomain { scheme, suffix } => write!(f, "{scheme}://*{suffix}"),
+        }
+    }
+}
+
+impl Serialize for OriginPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// CORS middleware allowing origins currently configured in `app_state`, so changes
+/// are applied on configuration reload. Handles preflight `OPTIONS` requests.
+#[must_use]
+pub fn cors(app_state: web::Data<AppState>) -> Cors {
+    Cors::default()
+        .allowed_origin_fn(move |origin, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| app_state.origin_allowed(origin))
+        })
+        .allowed_methods(vec!["GET", "POST", "DELETE"])
+        .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT])
+        .allowed_header(header::CONTENT_TYPE)
+        .allowed_header(REQUEST_ID_HEADER)
+        .expose_headers(vec![
+            header::RETRY_AFTER,
+            header::LINK,
+            header::HeaderName::from_static(REQUEST_ID_HEADER),
+            header::HeaderName::from_static("deprecation"),
+            header::HeaderName::from_static("sunset"),
+        ])
+        .max_age(3600)
+}
+
+/// Load allowed origins of registered clients now and every minute, so origins changed
+/// with `client` commands take effect without restart.
+pub async fn run_client_origins_task(app_state: web::Data<AppState>) {
+    let mut interval = actix_web::rt::time::interval(CLIENT_ORIGINS_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(err) = app_state.load_client_origins().await {
+            log::error!("Failed to load allowed origins of clients: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_origin_pattern() {
+        let exact: OriginPattern = "https://app.example.com/".parse().unwrap();
+        assert_eq!(
+            exact,
+            OriginPattern::Exact("https://app.example.com".into())
+        );
+        assert!(exact.matches("https://app.example.com"));
+        assert!(!exact.matches("https://app.example.com:8443"));
+        assert!(!exact.matches("http://app.example.com"));
+
+        let subdomain: OriginPattern = "https://*.example.com".parse().unwrap();
+        assert_eq!(subdomain.to_string(), "https://*.example.com");
+        assert!(subdomain.matches("https://preview-1.example.com"));
+        assert!(subdomain.matches("https://a.b.example.com"));
+        assert!(!subdomain.matches("https://example.com"));
+        assert!(!subdomain.matches("https://evil-example.com"));
+        assert!(!subdomain.matches("https://evil.com/.example.com"));
+        assert!(!subdomain.matches("http://app.example.com"));
+        assert!(!subdomain.matches("https://app.example.com:8443"));
+
+        let port: OriginPattern = "http://*.localhost:8000".parse().unwrap();
+        assert!(port.matches("http://app.localhost:8000"));
+        assert!(!port.matches("http://app.localhost"));
+
+        assert_eq!("*".parse(), Ok(OriginPattern::Any));
+        assert!("example.com".parse::<OriginPattern>().is_err());
+        assert!("https://app.example.com/login"
+            .parse::<OriginPattern>()
+            .is_err());
+    }
+}
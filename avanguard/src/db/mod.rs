@@ -1,11 +1,51 @@
 pub mod models;
 
-use sqlx::postgres::PgConnectOptions;
+use std::time::Duration as StdDuration;
+
+use sqlx::{
+    migrate::{MigrateError, Migrator},
+    postgres::{PgConnectOptions, PgPoolOptions},
+    query_scalar,
+};
 
 pub type DbPool = sqlx::postgres::PgPool;
 
+/// Delay before the first connection retry, doubled after every failed attempt.
+const INITIAL_RETRY_DELAY: StdDuration = StdDuration::from_secs(1);
+
+/// Maximum delay between connection attempts.
+const MAX_RETRY_DELAY: StdDuration = StdDuration::from_secs(30);
+
+/// Time a request waits for a free connection before failing with service unavailable.
+const ACQUIRE_TIMEOUT: StdDuration = StdDuration::from_secs(5);
+
+/// Migrations embedded in the binary.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
 /// Initializes and migrates postgres database. Returns DB pool object.
-pub async fn init_db(host: &str, port: u16, name: &str, user: &str, password: &str) -> DbPool {
+pub async fn init_db(
+    host: &str,
+    port: u16,
+    name: &str,
+    user: &str,
+    password: &str,
+    retries: u32,
+) -> Result<DbPool, sqlx::Error> {
+    let pool = connect_db(host, port, name, user, password, retries).await?;
+    migrate(&pool).await?;
+    Ok(pool)
+}
+
+/// Connects to postgres database without applying migrations. Connection is retried up to
+/// `retries` times with exponential backoff, so the service can start before the database.
+pub async fn connect_db(
+    host: &str,
+    port: u16,
+    name: &str,
+    user: &str,
+    password: &str,
+    retries: u32,
+) -> Result<DbPool, sqlx::Error> {
     log::debug!("Connecting to database {}:{}/{}", host, port, name);
     let opts = PgConnectOptions::new()
         .host(host)
@@ -13,15 +53,105 @@ pub async fn init_db(host: &str, port: u16, name: &str, user: &str, password: &s
         .username(user)
         .password(password)
         .database(name);
-    let pool = DbPool::connect_with(opts)
-        .await
-        .expect("Database connection failed");
-    sqlx::migrate!()
-        .run(&pool)
-        .await
-        .expect("Cannot run database migrations.");
+    let mut delay = INITIAL_RETRY_DELAY;
+    let mut attempt = 0;
+    let pool = loop {
+        let connection = PgPoolOptions::new()
+            .acquire_timeout(ACQUIRE_TIMEOUT)
+            .connect_with(opts.clone())
+            .await;
+        match connection {
+            Ok(pool) => break pool,
+            Err(err) if attempt < retries && is_unavailable(&err) => {
+                attempt += 1;
+                log::warn!(
+                    "Database {host}:{port}/{name} unavailable, retry {attempt}/{retries} in {}s: {err}",
+                    delay.as_secs()
+                );
+                actix_web::rt::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+            Err(err) => return Err(err),
+        }
+    };
     log::info!("Connected to database {}:{}/{}", host, port, name);
-    pool
+    Ok(pool)
+}
+
+/// Whether the error means the database can't be reached at the moment, as opposed to
+/// errors in queries or data.
+pub fn is_unavailable(err: &sqlx::Error) -> bool {
+    matches!(
+        err,
+        sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed
+    )
+}
+
+/// Whether the error is a violation of unique constraint, e.g. by a concurrent insert.
+pub fn is_unique_violation(err: &sqlx::Error) -> bool {
+    matches!(err, sqlx::Error::Database(err) if err.is_unique_violation())
+}
+
+/// Migration embedded in the binary and whether it's applied to the database.
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+/// Versions of migrations applied to the database, in order.
+async fn applied_migrations(pool: &DbPool) -> Result<Vec<i64>, sqlx::Error> {
+    // Migrations table doesn't exist until the first migration
+    let exists: bool = query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(pool)
+        .await?;
+    if !exists {
+        return Ok(Vec::new());
+    }
+    query_scalar("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version")
+        .fetch_all(pool)
+        .await
+}
+
+/// Status of all migrations embedded in the binary.
+pub async fn migration_status(pool: &DbPool) -> Result<Vec<MigrationStatus>, sqlx::Error> {
+    let applied = applied_migrations(pool).await?;
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.contains(&migration.version),
+        })
+        .collect())
+}
+
+/// Number of migrations embedded in the binary which are not applied to the database.
+pub async fn pending_migrations(pool: &DbPool) -> Result<usize, sqlx::Error> {
+    let status = migration_status(pool).await?;
+    Ok(status.iter().filter(|migration| !migration.applied).count())
+}
+
+/// Apply pending migrations.
+pub async fn migrate(pool: &DbPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// Revert `steps` latest applied migrations using their down scripts.
+/// Returns versions of reverted migrations, latest first.
+pub async fn rollback(pool: &DbPool, steps: usize) -> Result<Vec<i64>, MigrateError> {
+    let applied = applied_migrations(pool).await?;
+    let keep = applied.len().saturating_sub(steps);
+    // Migrations newer than target version are reverted
+    let target = keep.checked_sub(1).map_or(0, |index| applied[index]);
+    MIGRATOR.undo(pool, target).await?;
+    Ok(applied[keep..].iter().rev().copied().collect())
 }
 
-pub use models::{RefreshToken, Wallet};
+pub use models::{Client, Group, LoginEvent, RefreshToken, Session, Wallet};
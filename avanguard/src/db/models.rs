@@ -5,14 +5,17 @@ use secp256k1::{
     ecdsa::{RecoverableSignature, RecoveryId},
     Message, Secp256k1,
 };
-use sqlx::{query, query_as};
+use sqlx::{query, query_as, query_scalar, PgExecutor};
+use tracing::instrument;
+use utoipa::ToSchema;
 
 use crate::{
-    crypto::keccak256,
+    crypto::{hmac_sha256, keccak256},
     db::DbPool,
     error::Web3Error,
     hex::{hex_decode, to_lower_hex},
-    random::gen_alphanumeric,
+    lockout::LockoutPolicy,
+    random::gen_hex,
     CHALLENGE_TEMPLATE,
 };
 
@@ -24,12 +27,21 @@ pub struct Wallet {
     pub challenge_signature: Option<String>,
     pub creation_timestamp: NaiveDateTime,
     pub validation_timestamp: Option<NaiveDateTime>,
+    pub failed_login_attempts: i32,
+    pub locked_until: Option<NaiveDateTime>,
+    pub disabled_at: Option<NaiveDateTime>,
 }
 
 impl Wallet {
     #[must_use]
     pub fn new(address: String) -> Self {
-        let challenge_message = Self::format_challenge(&address, CHALLENGE_TEMPLATE);
+        Self::with_template(address, CHALLENGE_TEMPLATE)
+    }
+
+    /// Create wallet with challenge containing `template` message.
+    #[must_use]
+    pub fn with_template(address: String, template: &str) -> Self {
+        let challenge_message = Self::format_challenge(&address, template);
         Self {
             id: None,
             address,
@@ -37,24 +49,34 @@ impl Wallet {
             challenge_signature: None,
             creation_timestamp: Utc::now().naive_utc(),
             validation_timestamp: None,
+            failed_login_attempts: 0,
+            locked_until: None,
+            disabled_at: None,
         }
     }
 
+    #[instrument(skip_all)]
     pub fn verify_address(&self, message: &str, signature: &str) -> Result<bool, Web3Error> {
-        let address_array = hex_decode(&self.address).map_err(|_| Web3Error::Decode)?;
+        let address_array = hex_decode(&self.address).map_err(|_| Web3Error::InvalidAddress)?;
+        if address_array.len() != 20 {
+            return Err(Web3Error::InvalidAddress);
+        }
         let signature_array = hex_decode(signature).map_err(|_| Web3Error::Decode)?;
 
-        let typed_data: TypedData = serde_json::from_str(message).map_err(|_| Web3Error::Decode)?;
-        let hash_msg = typed_data.encode_eip712().map_err(|_| Web3Error::Decode)?;
+        let typed_data: TypedData =
+            serde_json::from_str(message).map_err(|_| Web3Error::InvalidMessage)?;
+        let hash_msg = typed_data
+            .encode_eip712()
+            .map_err(|_| Web3Error::InvalidMessage)?;
         let message = Message::from_slice(&hash_msg).map_err(|_| Web3Error::InvalidMessage)?;
         if signature_array.len() != 65 {
-            return Err(Web3Error::InvalidMessage);
+            return Err(Web3Error::SignatureLength(signature_array.len()));
         }
         let id = match signature_array[64] {
             0 | 27 => 0,
             1 | 28 => 1,
             v if v >= 35 => i32::from((v - 1) & 1),
-            _ => return Err(Web3Error::InvalidRecoveryId),
+            v => return Err(Web3Error::InvalidRecoveryId(v)),
         };
         let recovery_id = RecoveryId::from_i32(id).map_err(|_| Web3Error::ParseSignature)?;
         let recoverable_signature =
@@ -77,9 +99,10 @@ impl Wallet {
         }
     }
 
-    pub async fn set_signature(
+    #[instrument(skip_all)]
+    pub async fn set_signature<'e, E: PgExecutor<'e>>(
         &mut self,
-        pool: &DbPool,
+        executor: E,
         signature: &str,
     ) -> Result<(), sqlx::Error> {
         self.challenge_signature = Some(signature.into());
@@ -89,6 +112,89 @@ impl Wallet {
                 "UPDATE wallet SET challenge_signature = $1, validation_timestamp = $2 WHERE id = $3",
                 self.challenge_signature, self.validation_timestamp, id
             )
+            .execute(executor)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns time until which logins to this wallet are locked, if they are locked.
+    #[must_use]
+    pub fn locked_until(&self) -> Option<NaiveDateTime> {
+        self.locked_until
+            .filter(|locked_until| *locked_until > Utc::now().naive_utc())
+    }
+
+    /// Disable or enable login with the wallet.
+    #[instrument(skip_all)]
+    pub async fn set_disabled<'e, E: PgExecutor<'e>>(
+        &mut self,
+        executor: E,
+        disabled: bool,
+    ) -> Result<(), sqlx::Error> {
+        let disabled_at = disabled.then(|| Utc::now().naive_utc());
+        query!(
+            "UPDATE wallet SET disabled_at = $2 WHERE id = $1",
+            self.id,
+            disabled_at
+        )
+        .execute(executor)
+        .await?;
+        self.disabled_at = disabled_at;
+        Ok(())
+    }
+
+    /// Increment failed login attempts counter, lock the wallet if threshold has been reached.
+    /// Counter starts over if the last failed attempt is older than the longest lockout,
+    /// as for client IPs.
+    #[instrument(skip_all)]
+    pub async fn register_failed_login(
+        &mut self,
+        pool: &DbPool,
+        policy: &LockoutPolicy,
+    ) -> Result<(), sqlx::Error> {
+        if let Some(id) = self.id {
+            self.failed_login_attempts = query_scalar!(
+                "UPDATE wallet SET failed_login_attempts = CASE \
+                    WHEN last_failed_login_at IS NULL OR last_failed_login_at < $3 THEN 1 \
+                    ELSE failed_login_attempts + 1 END, \
+                last_failed_login_at = $2 \
+                WHERE id = $1 RETURNING failed_login_attempts",
+                id,
+                Utc::now().naive_utc(),
+                policy.counter_cutoff(),
+            )
+            .fetch_one(pool)
+            .await?;
+            if let Some(locked_until) = policy.locked_until(self.failed_login_attempts) {
+                log::warn!(
+                    "Locking wallet {} until {locked_until} after {} failed login attempts",
+                    self.address,
+                    self.failed_login_attempts
+                );
+                self.locked_until = Some(locked_until);
+                query!(
+                    "UPDATE wallet SET locked_until = $2 WHERE id = $1",
+                    id,
+                    self.locked_until
+                )
+                .execute(pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reset failed login attempts counter and lift the lockout.
+    #[instrument(skip_all)]
+    pub async fn clear_failed_logins(&mut self, pool: &DbPool) -> Result<(), sqlx::Error> {
+        self.failed_login_attempts = 0;
+        self.locked_until = None;
+        if let Some(id) = self.id {
+            query!(
+                "UPDATE wallet SET failed_login_attempts = 0, locked_until = NULL WHERE id = $1",
+                id
+            )
             .execute(pool)
             .await?;
         }
@@ -128,6 +234,29 @@ impl Wallet {
         .collect()
     }
 
+    /// Delete up to `limit` wallets which were created before `cutoff` and never signed
+    /// the challenge. Returns number of deleted wallets.
+    #[instrument(skip_all)]
+    pub async fn delete_unverified(
+        pool: &DbPool,
+        cutoff: NaiveDateTime,
+        limit: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let result = query!(
+            "DELETE FROM wallet WHERE id IN (SELECT id FROM wallet \
+            WHERE validation_timestamp IS NULL AND challenge_signature IS NULL \
+            AND creation_timestamp < $1 \
+            AND NOT EXISTS (SELECT 1 FROM refreshtoken WHERE wallet_id = wallet.id) \
+            LIMIT $2)",
+            cutoff,
+            limit
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    #[instrument(skip_all)]
     pub async fn find_by_address(
         pool: &DbPool,
         address: &str,
@@ -135,8 +264,8 @@ impl Wallet {
         query_as!(
             Self,
             "SELECT id \"id?\", address, challenge_message, challenge_signature, \
-            creation_timestamp, validation_timestamp FROM wallet \
-            WHERE address = $1",
+            creation_timestamp, validation_timestamp, failed_login_attempts, locked_until, \
+            disabled_at FROM wallet WHERE address = $1",
             address
         )
         .fetch_optional(pool)
@@ -151,59 +280,252 @@ pub fn hash_message<S: AsRef<[u8]>>(message: S) -> [u8; 32] {
     keccak256(&eth_message)
 }
 
+/// Login attempt of a wallet.
+#[derive(Model, Serialize)]
+#[table(login_event)]
+pub struct LoginEvent {
+    pub(crate) id: Option<i64>,
+    pub wallet_id: i64,
+    pub timestamp: NaiveDateTime,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub client_id: String,
+    pub method: String,
+    pub outcome: String,
+}
+
+impl LoginEvent {
+    /// Verification of EIP-712 challenge signature.
+    pub const METHOD_EIP712: &'static str = "eip712";
+
+    #[must_use]
+    pub fn new(
+        wallet_id: i64,
+        ip: Option<String>,
+        user_agent: Option<String>,
+        client_id: String,
+        method: &str,
+        outcome: &str,
+    ) -> Self {
+        Self {
+            id: None,
+            wallet_id,
+            timestamp: Utc::now().naive_utc(),
+            ip,
+            user_agent,
+            client_id,
+            method: method.into(),
+            outcome: outcome.into(),
+        }
+    }
+
+    /// Login history of a wallet, most recent first.
+    #[instrument(skip_all)]
+    pub async fn find_by_wallet(
+        pool: &DbPool,
+        wallet_id: i64,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        query_as!(
+            Self,
+            "SELECT id \"id?\", wallet_id, timestamp, ip, user_agent, client_id, method, outcome \
+            FROM login_event WHERE wallet_id = $1 ORDER BY timestamp DESC, id DESC \
+            LIMIT $2 OFFSET $3",
+            wallet_id,
+            limit,
+            offset
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Delete up to `limit` events older than `cutoff`. Returns number of deleted events.
+    #[instrument(skip_all)]
+    pub async fn delete_old(
+        pool: &DbPool,
+        cutoff: NaiveDateTime,
+        limit: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let result = query!(
+            "DELETE FROM login_event WHERE id IN (SELECT id FROM login_event \
+            WHERE timestamp < $1 LIMIT $2)",
+            cutoff,
+            limit
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// Successful login whose refresh token family has a token which can still be used.
+#[derive(Serialize, ToSchema)]
+pub struct Session {
+    pub login_event_id: i64,
+    pub logged_in_at: NaiveDateTime,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub client_id: String,
+    pub method: String,
+    pub expires_at: NaiveDateTime,
+}
+
+impl Session {
+    /// Active sessions of a wallet, most recent first.
+    #[instrument(skip_all)]
+    pub async fn find_by_wallet(pool: &DbPool, wallet_id: i64) -> Result<Vec<Self>, sqlx::Error> {
+        query_as!(
+            Self,
+            "SELECT login_event.id login_event_id, login_event.timestamp logged_in_at, \
+            login_event.ip, login_event.user_agent, login_event.client_id, login_event.method, \
+            refreshtoken.expires_at \
+            FROM refreshtoken JOIN login_event ON login_event.id = refreshtoken.login_event_id \
+            WHERE refreshtoken.wallet_id = $1 AND refreshtoken.used_at IS NULL \
+            AND refreshtoken.blacklisted_at IS NULL AND refreshtoken.expires_at > $2 \
+            ORDER BY login_event.timestamp DESC",
+            wallet_id,
+            Utc::now().naive_utc()
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
 #[derive(Model, Debug)]
 pub struct RefreshToken {
     pub(crate) id: Option<i64>,
     pub wallet_id: i64,
-    pub token: String,
+    pub login_event_id: Option<i64>,
+    pub token_hash: String,
     pub expires_at: NaiveDateTime,
     pub used_at: Option<NaiveDateTime>,
     pub blacklisted_at: Option<NaiveDateTime>,
+    /// Space-delimited scopes requested at login, carried over to refreshed tokens.
+    pub scope: String,
 }
 
 impl RefreshToken {
+    /// Number of random bytes in refresh token (256 bits of entropy).
+    const TOKEN_BYTES: usize = 32;
+
+    /// Create new refresh token belonging to the family started by `login_event_id`.
+    /// Returns the model, which stores only keyed hash of the token, and the plaintext token
+    /// to be handed over to the client.
     #[must_use]
-    pub fn new(wallet_id: i64, expires_in: u32) -> Self {
+    pub fn new(
+        wallet_id: i64,
+        login_event_id: Option<i64>,
+        expires_in: u32,
+        secret: &[u8],
+    ) -> (Self, String) {
         let expiration = Utc::now() + Duration::seconds(expires_in.into());
-        Self {
+        let token = gen_hex(Self::TOKEN_BYTES);
+        let refresh_token = Self {
             id: None,
             wallet_id,
-            token: gen_alphanumeric(24),
+            login_event_id,
+            token_hash: Self::hash_token(secret, &token),
             expires_at: expiration.naive_utc(),
             used_at: None,
             blacklisted_at: None,
-        }
+            scope: String::new(),
+        };
+        (refresh_token, token)
     }
+
+    /// Compute keyed hash of plaintext refresh token.
+    #[must_use]
+    pub fn hash_token(secret: &[u8], token: &str) -> String {
+        to_lower_hex(&hmac_sha256(secret, token.as_bytes()))
+    }
+
     #[must_use]
     pub fn is_expired(&self) -> bool {
         self.expires_at < Utc::now().naive_utc()
     }
 
     /// Blacklist token
+    #[instrument(skip_all)]
     pub async fn blacklist(&self, pool: &DbPool) -> Result<(), sqlx::Error> {
         let blacklisted_time = Utc::now().naive_utc();
         query!(
             "UPDATE refreshtoken SET blacklisted_at = $2 \
-            WHERE token = $1",
-            self.token,
+            WHERE token_hash = $1",
+            self.token_hash,
             blacklisted_time
         )
         .execute(pool)
         .await?;
         Ok(())
     }
-    /// Find by refresh token.
+
+    /// Blacklist usable tokens of the family started by `login_event_id`.
+    /// Returns number of blacklisted tokens, 0 if the session has ended already.
+    #[instrument(skip_all)]
+    pub async fn blacklist_family<'e, E: PgExecutor<'e>>(
+        executor: E,
+        wallet_id: i64,
+        login_event_id: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let result = query!(
+            "UPDATE refreshtoken SET blacklisted_at = $3 \
+            WHERE wallet_id = $1 AND login_event_id = $2 AND blacklisted_at IS NULL \
+            AND used_at IS NULL AND expires_at > $3",
+            wallet_id,
+            login_event_id,
+            Utc::now().naive_utc()
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Blacklist all usable tokens of a wallet. Returns number of blacklisted tokens.
+    #[instrument(skip_all)]
+    pub async fn blacklist_wallet<'e, E: PgExecutor<'e>>(
+        executor: E,
+        wallet_id: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let result = query!(
+            "UPDATE refreshtoken SET blacklisted_at = $2 \
+            WHERE wallet_id = $1 AND blacklisted_at IS NULL AND used_at IS NULL \
+            AND expires_at > $2",
+            wallet_id,
+            Utc::now().naive_utc()
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Delete all tokens of a wallet. Returns number of deleted tokens.
+    #[instrument(skip_all)]
+    pub async fn delete_by_wallet<'e, E: PgExecutor<'e>>(
+        executor: E,
+        wallet_id: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let result = query!("DELETE FROM refreshtoken WHERE wallet_id = $1", wallet_id)
+            .execute(executor)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Find by plaintext refresh token.
+    #[instrument(skip_all)]
     pub async fn find_refresh_token(
         pool: &DbPool,
+        secret: &[u8],
         token: &str,
     ) -> Result<Option<Self>, sqlx::Error> {
+        let token_hash = Self::hash_token(secret, token);
         match query_as!(
             Self,
-            r#"SELECT id "id?", wallet_id, token, expires_at, blacklisted_at, used_at "used_at?"
-            FROM refreshtoken WHERE token = $1 
-            AND blacklisted_at IS NULL 
+            r#"SELECT id "id?", wallet_id, login_event_id, token_hash, expires_at, blacklisted_at, used_at "used_at?", scope
+            FROM refreshtoken WHERE token_hash = $1
+            AND blacklisted_at IS NULL
             AND used_at IS NULL"#,
-            token
+            token_hash
         )
         .fetch_optional(pool)
         .await
@@ -211,8 +533,8 @@ impl RefreshToken {
             Ok(Some(token)) => {
                 if token.is_expired() {
                     log::debug!(
-                        "Found token: {} but expired at: {} removing it from database",
-                        token.token,
+                        "Found token with id: {:?} but expired at: {} removing it from database",
+                        token.id,
                         token.expires_at
                     );
                     token.delete(pool).await?;
@@ -225,24 +547,244 @@ impl RefreshToken {
             Err(err) => Err(err),
         }
     }
-    /// Mark token as used
-    pub async fn set_used(&mut self, pool: &DbPool) -> Result<(), sqlx::Error> {
+    /// Delete up to `limit` tokens which expired before `cutoff`.
+    /// Returns number of deleted tokens.
+    #[instrument(skip_all)]
+    pub async fn delete_expired(
+        pool: &DbPool,
+        cutoff: NaiveDateTime,
+        limit: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let result = query!(
+            "DELETE FROM refreshtoken WHERE id IN (SELECT id FROM refreshtoken \
+            WHERE expires_at < $1 LIMIT $2)",
+            cutoff,
+            limit
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Delete up to `limit` tokens which were used or blacklisted before `cutoff`.
+    /// Returns number of deleted tokens.
+    #[instrument(skip_all)]
+    pub async fn delete_revoked(
+        pool: &DbPool,
+        cutoff: NaiveDateTime,
+        limit: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let result = query!(
+            "DELETE FROM refreshtoken WHERE id IN (SELECT id FROM refreshtoken \
+            WHERE used_at < $1 OR blacklisted_at < $1 LIMIT $2)",
+            cutoff,
+            limit
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Mark token as used. Returns `false` if the token was used, blacklisted or expired since
+    /// it was looked up, e.g. by a concurrent refresh or revocation.
+    #[instrument(skip_all)]
+    pub async fn set_used<'e, E: PgExecutor<'e>>(
+        &mut self,
+        executor: E,
+    ) -> Result<bool, sqlx::Error> {
         let used_at = Utc::now().naive_utc();
-        query!(
+        let result = query!(
             "UPDATE refreshtoken SET used_at = $2 \
-            WHERE token = $1",
-            self.token,
-            Some(used_at),
+            WHERE id = $1 AND used_at IS NULL AND blacklisted_at IS NULL AND expires_at > $2",
+            self.id,
+            used_at,
         )
-        .execute(pool)
+        .execute(executor)
         .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        self.used_at = Some(used_at);
         log::info!(
-            "Marked token: {} for user with id: {} at date: {:?}",
-            self.token,
+            "Marked token with id: {:?} for user with id: {} as used at date: {:?}",
+            self.id,
             self.wallet_id,
             self.used_at,
         );
-        Ok(())
+        Ok(true)
+    }
+}
+
+/// Group of wallets, emitted in the groups claim of tokens requested with `groups` scope.
+#[derive(Model, Serialize)]
+pub struct Group {
+    pub(crate) id: Option<i64>,
+    #[model(ref)]
+    pub name: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl Group {
+    #[must_use]
+    pub fn new(name: String) -> Self {
+        Self {
+            id: None,
+            name,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    /// Whether `name` can be used as group name, which must be a non-empty path segment.
+    #[must_use]
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && !name.contains(|c: char| c.is_whitespace() || c == '/')
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_by_name<'e, E: PgExecutor<'e>>(
+        executor: E,
+        name: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        query_as!(
+            Self,
+            "SELECT id \"id?\", name, created_at FROM \"group\" WHERE name = $1",
+            name
+        )
+        .fetch_optional(executor)
+        .await
+    }
+
+    /// Names of groups the wallet belongs to, sorted.
+    #[instrument(skip_all)]
+    pub async fn names_by_wallet<'e, E: PgExecutor<'e>>(
+        executor: E,
+        wallet_id: i64,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        query_scalar!(
+            "SELECT name FROM \"group\" JOIN wallet_group ON wallet_group.group_id = \"group\".id \
+            WHERE wallet_group.wallet_id = $1 ORDER BY name",
+            wallet_id
+        )
+        .fetch_all(executor)
+        .await
+    }
+
+    /// Addresses of wallets in the group, sorted.
+    #[instrument(skip_all)]
+    pub async fn members<'e, E: PgExecutor<'e>>(
+        &self,
+        executor: E,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        query_scalar!(
+            "SELECT address FROM wallet JOIN wallet_group ON wallet_group.wallet_id = wallet.id \
+            WHERE wallet_group.group_id = $1 ORDER BY address",
+            self.id
+        )
+        .fetch_all(executor)
+        .await
+    }
+
+    /// Add wallet to the group. Returns whether it wasn't a member already.
+    #[instrument(skip_all)]
+    pub async fn add_wallet<'e, E: PgExecutor<'e>>(
+        &self,
+        executor: E,
+        wallet_id: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let result = query!(
+            "INSERT INTO wallet_group (wallet_id, group_id, created_at) VALUES ($1, $2, $3) \
+            ON CONFLICT DO NOTHING",
+            wallet_id,
+            self.id,
+            Utc::now().naive_utc()
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Remove wallet from the group. Returns whether it was a member.
+    #[instrument(skip_all)]
+    pub async fn remove_wallet<'e, E: PgExecutor<'e>>(
+        &self,
+        executor: E,
+        wallet_id: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let result = query!(
+            "DELETE FROM wallet_group WHERE wallet_id = $1 AND group_id = $2",
+            wallet_id,
+            self.id
+        )
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// OIDC client registered in addition to the client configured with `client_id`.
+/// ID tokens issued to the client are signed with its secret.
+#[derive(Model, Serialize)]
+pub struct Client {
+    pub(crate) id: Option<i64>,
+    #[model(ref)]
+    pub client_id: String,
+    #[model(ref)]
+    pub name: String,
+    #[serde(skip)]
+    #[model(ref)]
+    pub secret: String,
+    pub created_at: NaiveDateTime,
+    /// Origins allowed to make CORS requests besides configured `client_origin_url`.
+    #[model(ref)]
+    pub allowed_origins: Vec<String>,
+}
+
+impl Client {
+    /// Number of random bytes in client secret.
+    const SECRET_BYTES: usize = 32;
+
+    /// Create client with random secret.
+    #[must_use]
+    pub fn new(client_id: String, name: String) -> Self {
+        Self {
+            id: None,
+            client_id,
+            name,
+            secret: gen_hex(Self::SECRET_BYTES),
+            created_at: Utc::now().naive_utc(),
+            allowed_origins: Vec::new(),
+        }
+    }
+
+    /// Replace secret with a new random one. Tokens signed with the old secret are
+    /// no longer valid once the client is saved.
+    pub fn rotate_secret(&mut self) {
+        self.secret = gen_hex(Self::SECRET_BYTES);
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_by_client_id<'e, E: PgExecutor<'e>>(
+        executor: E,
+        client_id: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        query_as!(
+            Self,
+            "SELECT id \"id?\", client_id, name, secret, created_at, allowed_origins \
+            FROM client WHERE client_id = $1",
+            client_id
+        )
+        .fetch_optional(executor)
+        .await
+    }
+
+    /// Allowed origins of all clients.
+    #[instrument(skip_all)]
+    pub async fn all_origins<'e, E: PgExecutor<'e>>(
+        executor: E,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        query_scalar!("SELECT DISTINCT unnest(allowed_origins) \"origin!\" FROM client")
+            .fetch_all(executor)
+            .await
     }
 }
 
@@ -270,4 +812,47 @@ mod test {
             assert!(result);
         }
     }
+
+    #[test]
+    fn test_verify_address_errors() {
+        let address = "0x6cD15DA14A4Ef26047f1D7858D7A82b59DDCa102";
+        let message = Wallet::format_challenge(address, CHALLENGE_TEMPLATE);
+        let wallet = Wallet::new(address.into());
+        let signature = "0xfb812c61b3d5f3ea729a049b4f14c28c07938367c91062c959150e1a3273f07772f162c5abf8312be39c3a6640c47e02866bcd19b5545bc5650d5870547a1a8f";
+        assert!(matches!(
+            wallet.verify_address(&message, "0xzz"),
+            Err(Web3Error::Decode)
+        ));
+        assert!(matches!(
+            wallet.verify_address(&message, "0x00"),
+            Err(Web3Error::SignatureLength(1))
+        ));
+        assert!(matches!(
+            wallet.verify_address(&message, &format!("{signature}05")),
+            Err(Web3Error::InvalidRecoveryId(5))
+        ));
+        assert!(matches!(
+            wallet.verify_address("{}", &format!("{signature}1c")),
+            Err(Web3Error::InvalidMessage)
+        ));
+        assert!(matches!(
+            Wallet::new("0x01".into()).verify_address(&message, &format!("{signature}1c")),
+            Err(Web3Error::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    fn test_refresh_token_hash() {
+        let (refresh_token, token) = RefreshToken::new(1, None, 60, b"secret");
+        assert_eq!(token.len(), 64);
+        assert_ne!(refresh_token.token_hash, token);
+        assert_eq!(
+            refresh_token.token_hash,
+            RefreshToken::hash_token(b"secret", &token)
+        );
+        assert_ne!(
+            refresh_token.token_hash,
+            RefreshToken::hash_token(b"other secret", &token)
+        );
+    }
 }
@@ -1,9 +1,19 @@
 use actix_web::{
-    http::{header::ContentType, StatusCode},
+    http::{
+        header::{self, ContentType},
+        StatusCode,
+    },
     HttpResponse, ResponseError,
 };
+use chrono::{NaiveDateTime, Utc};
 use openidconnect::JsonWebTokenError;
 use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{claims::ClaimsError, db::is_unavailable, telemetry::current_request_id};
+
+/// Seconds after which requests failed due to unavailable database may be retried.
+const DB_RETRY_AFTER: u64 = 5;
 
 #[derive(Debug, Error)]
 pub enum ApiError {
@@ -13,45 +23,137 @@ pub enum ApiError {
     WalletNotFound,
     #[error("refresh token not found")]
     TokenNotFound,
-    #[error("signature incorrect")]
-    SignatureIncorrect,
+    #[error("invalid signature: {0}")]
+    Signature(#[from] Web3Error),
     #[error("signing error")]
     SigningError(#[from] JsonWebTokenError),
+    #[error("rate limit exceeded, retry after {0} seconds")]
+    RateLimited(u64),
+    #[error("wallet locked until {0}")]
+    WalletLocked(NaiveDateTime),
+    #[error("unauthorized")]
+    Unauthorized,
+    #[error("session not found")]
+    SessionNotFound,
+    #[error("webhook not found")]
+    WebhookNotFound,
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("wallet disabled")]
+    WalletDisabled,
+    #[error("client not found")]
+    ClientNotFound,
+    #[error(transparent)]
+    Claims(#[from] ClaimsError),
+    #[error("group not found")]
+    GroupNotFound,
+    #[error("group already exists")]
+    GroupExists,
+    #[error("wallet is not a member of the group")]
+    NotGroupMember,
 }
 
 impl ApiError {
     pub fn code(&self) -> &str {
         match self {
+            Self::Sqlx(err) if is_unavailable(err) => "DatabaseUnavailable",
             Self::Sqlx(_) => "DB",
             Self::WalletNotFound => "WalletNotFound",
-            Self::SignatureIncorrect => "SignatureIncorrect",
+            Self::Signature(err) => err.code(),
             Self::SigningError(_) => "SigningError",
             Self::TokenNotFound => "TokenNotFound",
+            Self::RateLimited(_) => "RateLimited",
+            Self::WalletLocked(_) => "WalletLocked",
+            Self::Unauthorized => "Unauthorized",
+            Self::SessionNotFound => "SessionNotFound",
+            Self::WebhookNotFound => "WebhookNotFound",
+            Self::InvalidRequest(_) => "InvalidRequest",
+            Self::WalletDisabled => "WalletDisabled",
+            Self::ClientNotFound => "ClientNotFound",
+            Self::Claims(_) => "Claims",
+            Self::GroupNotFound => "GroupNotFound",
+            Self::GroupExists => "GroupExists",
+            Self::NotGroupMember => "NotGroupMember",
         }
     }
 
     pub fn message(&self) -> String {
         match self {
+            Self::Sqlx(err) if is_unavailable(err) => {
+                String::from("Service temporarily unavailable")
+            }
             Self::Sqlx(_) => String::from("Internal error"),
             Self::WalletNotFound => String::from("Wallet not found"),
-            Self::SignatureIncorrect => String::from("Signature incorrect"),
+            Self::Signature(err) => err.message(),
             Self::SigningError(_) => String::from("Signing error"),
             Self::TokenNotFound => String::from("Refresh token not found"),
+            Self::RateLimited(_) => String::from("Too many requests"),
+            Self::WalletLocked(_) => {
+                String::from("Wallet locked due to too many failed login attempts")
+            }
+            Self::Unauthorized => String::from("Unauthorized"),
+            Self::SessionNotFound => String::from("Session not found"),
+            Self::WebhookNotFound => String::from("Webhook not found"),
+            Self::InvalidRequest(message) => message.clone(),
+            Self::WalletDisabled => String::from("Wallet disabled"),
+            Self::ClientNotFound => String::from("Client not found"),
+            Self::Claims(_) => String::from("Claims of the token cannot be retrieved"),
+            Self::GroupNotFound => String::from("Group not found"),
+            Self::GroupExists => String::from("Group already exists"),
+            Self::NotGroupMember => String::from("Wallet is not a member of the group"),
+        }
+    }
+
+    /// Seconds after which the request may be retried.
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            Self::Sqlx(err) if is_unavailable(err) => Some(DB_RETRY_AFTER),
+            Self::RateLimited(retry_after) => Some(*retry_after),
+            Self::WalletLocked(locked_until) => {
+                let seconds = (*locked_until - Utc::now().naive_utc()).num_seconds();
+                Some(seconds.max(1) as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Response of unversioned paths, in format used before versioning: signature errors are
+    /// all `SignatureIncorrect` and refresh errors aren't in OAuth 2.0 format.
+    pub(crate) fn legacy_error_response(&self) -> HttpResponse {
+        match self {
+            Self::Signature(_) => Self::Signature(Web3Error::Recovery).error_response(),
+            _ => self.error_response(),
         }
     }
 }
 
-#[derive(Serialize)]
-struct ErrorInfo {
+/// Body of error responses.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ErrorInfo {
+    /// Error code, e.g. `SignatureIncorrect`.
+    #[schema(example = "SignatureIncorrect")]
     error: String,
+    /// Human-readable description.
     message: String,
+    /// End of wallet lockout, for `WalletLocked` errors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    locked_until: Option<NaiveDateTime>,
+    /// Id of the request, to correlate with server logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 impl From<&ApiError> for ErrorInfo {
     fn from(api_error: &ApiError) -> Self {
+        let locked_until = match api_error {
+            ApiError::WalletLocked(locked_until) => Some(*locked_until),
+            _ => None,
+        };
         Self {
             error: api_error.code().into(),
             message: api_error.message(),
+            locked_until,
+            request_id: current_request_id(),
         }
     }
 }
@@ -59,30 +161,111 @@ impl From<&ApiError> for ErrorInfo {
 impl ResponseError for ApiError {
     /// Return error as JSON.
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .json(ErrorInfo::from(self))
+        let mut response = HttpResponse::build(self.status_code());
+        response.insert_header(ContentType::json());
+        if let Some(retry_after) = self.retry_after() {
+            response.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+        response.json(ErrorInfo::from(self))
     }
 
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Sqlx(err) if is_unavailable(err) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Sqlx(_) | Self::Claims(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Signature(Web3Error::InvalidMessage) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Signature(Web3Error::Recovery | Web3Error::VerifyAddress) => {
+                StatusCode::UNAUTHORIZED
+            }
+            Self::Signature(_) => StatusCode::BAD_REQUEST,
             ApiError::WalletNotFound
-            | ApiError::SignatureIncorrect
             | ApiError::SigningError(_)
-            | ApiError::TokenNotFound => StatusCode::UNAUTHORIZED,
+            | ApiError::TokenNotFound
+            | ApiError::Unauthorized
+            | ApiError::ClientNotFound => StatusCode::UNAUTHORIZED,
+            ApiError::WalletDisabled => StatusCode::FORBIDDEN,
+            ApiError::RateLimited(_) | ApiError::WalletLocked(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::SessionNotFound
+            | ApiError::WebhookNotFound
+            | ApiError::GroupNotFound
+            | ApiError::NotGroupMember => StatusCode::NOT_FOUND,
+            ApiError::GroupExists => StatusCode::CONFLICT,
+            ApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// Error of endpoints implementing OAuth 2.0 grants, returned in RFC 6749 format.
+#[derive(Debug, Error)]
+#[error(transparent)]
+pub struct OAuthError(#[from] pub ApiError);
+
+/// Body of OAuth 2.0 error responses, RFC 6749 section 5.2.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct OAuthErrorInfo {
+    /// Error code, e.g. `invalid_grant`.
+    #[schema(example = "invalid_grant")]
+    error: &'static str,
+    /// Human-readable description.
+    error_description: String,
+    /// Id of the request, to correlate with server logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl OAuthError {
+    /// RFC 6749 error code.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match &self.0 {
+            ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::ClientNotFound => "invalid_client",
+            ApiError::TokenNotFound | ApiError::WalletNotFound | ApiError::WalletDisabled => {
+                "invalid_grant"
+            }
+            ApiError::Sqlx(err) if is_unavailable(err) => "temporarily_unavailable",
+            ApiError::RateLimited(_) => "temporarily_unavailable",
+            _ => "server_error",
+        }
+    }
+}
+
+impl ResponseError for OAuthError {
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        response.insert_header(ContentType::json());
+        response.insert_header((header::CACHE_CONTROL, "no-store"));
+        if let Some(retry_after) = self.0.retry_after() {
+            response.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+        response.json(OAuthErrorInfo {
+            error: self.code(),
+            error_description: self.0.message(),
+            request_id: current_request_id(),
+        })
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self.code() {
+            "invalid_request" | "invalid_grant" => StatusCode::BAD_REQUEST,
+            "invalid_client" => StatusCode::UNAUTHORIZED,
+            _ => self.0.status_code(),
         }
     }
 }
 
 #[derive(Debug, Error)]
 pub enum Web3Error {
-    #[error("hex decoding error")]
+    #[error("signature hex decoding error")]
     Decode,
+    #[error("invalid wallet address")]
+    InvalidAddress,
     #[error("invalid message")]
     InvalidMessage,
-    #[error("invalid recovery id")]
-    InvalidRecoveryId,
+    #[error("invalid signature length {0}")]
+    SignatureLength(usize),
+    #[error("invalid recovery id {0}")]
+    InvalidRecoveryId(u8),
     #[error("error parsing signature")]
     ParseSignature,
     #[error("recovery error")]
@@ -91,6 +274,41 @@ pub enum Web3Error {
     VerifyAddress,
 }
 
+impl Web3Error {
+    /// Error code telling apart malformed input, unsupported signature format and signatures
+    /// made by another wallet.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Decode | Self::ParseSignature => "MalformedSignature",
+            Self::InvalidAddress => "InvalidAddress",
+            Self::InvalidMessage => "InvalidChallenge",
+            Self::SignatureLength(_) | Self::InvalidRecoveryId(_) => "UnsupportedSignatureFormat",
+            Self::Recovery => "SignatureIncorrect",
+            Self::VerifyAddress => "SignerMismatch",
+        }
+    }
+
+    #[must_use]
+    pub fn message(&self) -> String {
+        match self {
+            Self::Decode => String::from("Signature must be a hex string"),
+            Self::InvalidAddress => String::from("Wallet address must be 20 bytes hex string"),
+            Self::InvalidMessage => String::from("Challenge cannot be verified"),
+            Self::SignatureLength(length) => format!(
+                "Signature must be 65 bytes long (r, s, v), got {length} bytes; \
+                compact EIP-2098 signatures aren't supported"
+            ),
+            Self::InvalidRecoveryId(v) => {
+                format!("Unsupported recovery id {v}, expected 0, 1, 27, 28 or EIP-155 value")
+            }
+            Self::ParseSignature => String::from("Signature is not a valid secp256k1 signature"),
+            Self::Recovery => String::from("Signature incorrect"),
+            Self::VerifyAddress => String::from("Signature was made by a different wallet"),
+        }
+    }
+}
+
 #[derive(Debug, Error, PartialEq)]
 pub enum HexError {
     #[error("Invalid character {0}")]
@@ -0,0 +1,430 @@
+use std::time::Duration as StdDuration;
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, SecondsFormat, Utc};
+use openidconnect::url::Url;
+use sqlx::{query, query_as, query_scalar, PgConnection};
+use thiserror::Error;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{db::DbPool, webhook, Config};
+
+/// Version of event JSON format, incremented on incompatible changes.
+pub const EVENT_VERSION: u32 = 1;
+
+/// Postgres advisory lock key held while publishing, so events are published in order
+/// by a single instance.
+const PUBLISH_LOCK_KEY: i64 = 0x6176_616e_6576_6e74;
+
+/// Maximum number of events published at once.
+const PUBLISH_BATCH_SIZE: i64 = 100;
+
+/// Event sinks enabled with crate features.
+const ENABLED_SINKS: &[&str] = &[
+    #[cfg(feature = "nats")]
+    "nats",
+    #[cfg(feature = "kafka")]
+    "kafka",
+];
+
+/// Authentication event published to message bus and delivered to webhooks.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", content = "data")]
+pub enum AuthEvent {
+    #[serde(rename = "wallet.registered")]
+    WalletRegistered { address: String },
+    #[serde(rename = "login.succeeded")]
+    LoginSucceeded {
+        address: String,
+        session_id: Option<i64>,
+    },
+    #[serde(rename = "token.refreshed")]
+    TokenRefreshed {
+        address: String,
+        session_id: Option<i64>,
+    },
+    #[serde(rename = "session.revoked")]
+    SessionRevoked {
+        address: String,
+        session_id: Option<i64>,
+        count: u64,
+    },
+    #[serde(rename = "wallet.deleted")]
+    WalletDeleted { address: String },
+}
+
+impl AuthEvent {
+    /// Names of all events.
+    pub const NAMES: [&'static str; 5] = [
+        "wallet.registered",
+        "login.succeeded",
+        "token.refreshed",
+        "session.revoked",
+        "wallet.deleted",
+    ];
+
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::WalletRegistered { .. } => Self::NAMES[0],
+            Self::LoginSucceeded { .. } => Self::NAMES[1],
+            Self::TokenRefreshed { .. } => Self::NAMES[2],
+            Self::SessionRevoked { .. } => Self::NAMES[3],
+            Self::WalletDeleted { .. } => Self::NAMES[4],
+        }
+    }
+
+    /// Address of the wallet the event concerns.
+    #[must_use]
+    pub fn address(&self) -> &str {
+        match self {
+            Self::WalletRegistered { address }
+            | Self::WalletDeleted { address }
+            | Self::LoginSucceeded { address, .. }
+            | Self::TokenRefreshed { address, .. }
+            | Self::SessionRevoked { address, .. } => address,
+        }
+    }
+}
+
+/// Versioned JSON representation of an event.
+#[derive(Serialize)]
+pub struct EventEnvelope<'a> {
+    pub version: u32,
+    /// Unique event id, allows consumers to discard duplicates.
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub event: &'a AuthEvent,
+    pub timestamp: String,
+}
+
+impl<'a> EventEnvelope<'a> {
+    #[must_use]
+    pub fn new(event: &'a AuthEvent) -> Self {
+        Self {
+            version: EVENT_VERSION,
+            id: Uuid::new_v4(),
+            event,
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize event")
+    }
+}
+
+/// Callback notified about events in process, e.g. by applications embedding Avanguard.
+pub trait EventHook: Send + Sync {
+    /// Called after the change the event describes is committed. Runs on the request path,
+    /// so longer work should be spawned.
+    fn on_event(&self, event: &AuthEvent);
+}
+
+impl<F: Fn(&AuthEvent) + Send + Sync> EventHook for F {
+    fn on_event(&self, event: &AuthEvent) {
+        self(event);
+    }
+}
+
+/// Queue event for publishing and webhook delivery. Call within the transaction making
+/// the change the event describes, so the event is emitted only if the change is committed.
+#[instrument(skip_all, fields(event = event.name()))]
+pub async fn emit(
+    conn: &mut PgConnection,
+    config: &Config,
+    event: &AuthEvent,
+) -> Result<(), sqlx::Error> {
+    let payload = EventEnvelope::new(event).to_json();
+    if config.event_sink.is_some() {
+        query!(
+            "INSERT INTO event_outbox (event, key, payload, created_at) VALUES ($1, $2, $3, $4)",
+            event.name(),
+            event.address(),
+            payload,
+            Utc::now().naive_utc(),
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    webhook::enqueue(&mut *conn, event.name(), &payload).await?;
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+#[error("failed to publish event: {0}")]
+pub struct PublishError(pub String);
+
+/// Message bus events are published to.
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    /// Publish event JSON `payload`. `key` is address of the wallet the event concerns.
+    async fn publish(&self, event: &str, key: &str, payload: &str) -> Result<(), PublishError>;
+}
+
+/// Publishes events to NATS subjects `<topic>.<event>`, e.g. `avanguard.login.succeeded`.
+#[cfg(feature = "nats")]
+pub struct NatsSink {
+    client: async_nats::Client,
+    topic: String,
+}
+
+#[cfg(feature = "nats")]
+impl NatsSink {
+    pub async fn connect(url: &Url, topic: &str) -> Result<Self, PublishError> {
+        let address = format!(
+            "{}:{}",
+            url.host_str().unwrap_or("localhost"),
+            url.port().unwrap_or(4222)
+        );
+        let client = async_nats::connect(address)
+            .await
+            .map_err(|err| PublishError(err.to_string()))?;
+        Ok(Self {
+            client,
+            topic: topic.into(),
+        })
+    }
+}
+
+#[cfg(feature = "nats")]
+#[async_trait]
+impl EventSink for NatsSink {
+    async fn publish(&self, event: &str, _key: &str, payload: &str) -> Result<(), PublishError> {
+        self.client
+            .publish(
+                format!("{}.{event}", self.topic),
+                payload.to_string().into(),
+            )
+            .await
+            .map_err(|err| PublishError(err.to_string()))?;
+        // Make sure the message reached the server before the event is marked as published
+        self.client
+            .flush()
+            .await
+            .map_err(|err| PublishError(err.to_string()))
+    }
+}
+
+/// Publishes events to Kafka topic, keyed with wallet address. The partition is chosen
+/// by hash of the key, so events of a wallet are consumed in order.
+#[cfg(feature = "kafka")]
+pub struct KafkaSink {
+    partitions: Vec<rskafka::client::partition::PartitionClient>,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaSink {
+    pub async fn connect(url: &Url, topic: &str) -> Result<Self, PublishError> {
+        let broker = format!(
+            "{}:{}",
+            url.host_str().unwrap_or("localhost"),
+            url.port().unwrap_or(9092)
+        );
+        let client = rskafka::client::ClientBuilder::new(vec![broker])
+            .build()
+            .await
+            .map_err(|err| PublishError(err.to_string()))?;
+        let partition_ids: Vec<i32> = client
+            .list_topics()
+            .await
+            .map_err(|err| PublishError(err.to_string()))?
+            .into_iter()
+            .find(|t| t.name == topic)
+            .map(|t| t.partitions.into_iter().collect())
+            .filter(|partitions: &Vec<i32>| !partitions.is_empty())
+            // Topic doesn't exist yet, brokers create it with the first record
+            .unwrap_or_else(|| vec![0]);
+        let mut partitions = Vec::with_capacity(partition_ids.len());
+        for partition in partition_ids {
+            partitions.push(
+                client
+                    .partition_client(
+                        topic,
+                        partition,
+                        rskafka::client::partition::UnknownTopicHandling::Retry,
+                    )
+                    .await
+                    .map_err(|err| PublishError(err.to_string()))?,
+            );
+        }
+        Ok(Self { partitions })
+    }
+}
+
+/// Index of the partition of `key` among `count` partitions, stable across restarts.
+#[cfg(feature = "kafka")]
+fn partition_index(key: &str, count: usize) -> usize {
+    let hash = crate::crypto::keccak256(key.as_bytes());
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]) as usize % count
+}
+
+#[cfg(feature = "kafka")]
+#[async_trait]
+impl EventSink for KafkaSink {
+    async fn publish(&self, event: &str, key: &str, payload: &str) -> Result<(), PublishError> {
+        let record = rskafka::record::Record {
+            key: Some(key.as_bytes().to_vec()),
+            value: Some(payload.as_bytes().to_vec()),
+            headers: [(String::from("event"), event.as_bytes().to_vec())].into(),
+            timestamp: Utc::now(),
+        };
+        self.partitions[partition_index(key, self.partitions.len())]
+            .produce(
+                vec![record],
+                rskafka::client::partition::Compression::NoCompression,
+            )
+            .await
+            .map_err(|err| PublishError(err.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Connect to message bus given by `nats://` or `kafka://` URL.
+#[cfg_attr(not(any(feature = "nats", feature = "kafka")), allow(unused_variables))]
+pub async fn connect_sink(url: &Url, topic: &str) -> Result<Box<dyn EventSink>, PublishError> {
+    match url.scheme() {
+        #[cfg(feature = "nats")]
+        "nats" => Ok(Box::new(NatsSink::connect(url, topic).await?)),
+        #[cfg(feature = "kafka")]
+        "kafka" => Ok(Box::new(KafkaSink::connect(url, topic).await?)),
+        scheme => Err(PublishError(format!(
+            "unsupported event sink {scheme}, enabled sinks: {}",
+            ENABLED_SINKS.join(", ")
+        ))),
+    }
+}
+
+struct OutboxEvent {
+    id: i64,
+    event: String,
+    key: String,
+    payload: String,
+}
+
+/// Publish pending events in order of creation. Stops at the first failure, so the failed
+/// event is retried before any later one. Returns number of published events.
+#[instrument(skip_all)]
+pub async fn publish_pending(pool: &DbPool, sink: &dyn EventSink) -> Result<usize, sqlx::Error> {
+    // Session lock, so no transaction stays open while waiting for the sink
+    let mut conn = pool.acquire().await?;
+    let locked = query_scalar!(
+        "SELECT pg_try_advisory_lock($1) \"locked!\"",
+        PUBLISH_LOCK_KEY
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if !locked {
+        return Ok(0);
+    }
+    let result = publish_locked(&mut conn, sink).await;
+    let unlocked = query_scalar!(
+        "SELECT pg_advisory_unlock($1) \"unlocked!\"",
+        PUBLISH_LOCK_KEY
+    )
+    .fetch_one(&mut *conn)
+    .await;
+    if unlocked.is_err() {
+        // Closing the connection releases the lock instead
+        drop(conn.detach());
+    }
+    result
+}
+
+async fn publish_locked(
+    conn: &mut PgConnection,
+    sink: &dyn EventSink,
+) -> Result<usize, sqlx::Error> {
+    let events = query_as!(
+        OutboxEvent,
+        "SELECT id, event, key, payload FROM event_outbox WHERE published_at IS NULL \
+        ORDER BY id LIMIT $1",
+        PUBLISH_BATCH_SIZE
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut published = 0;
+    for event in events {
+        if let Err(err) = sink.publish(&event.event, &event.key, &event.payload).await {
+            log::warn!("Failed to publish event {}: {err}", event.id);
+            break;
+        }
+        // Marked one by one, so published events aren't published again if marking fails
+        query!(
+            "UPDATE event_outbox SET published_at = $2 WHERE id = $1",
+            event.id,
+            Utc::now().naive_utc()
+        )
+        .execute(&mut *conn)
+        .await?;
+        published += 1;
+    }
+    Ok(published)
+}
+
+/// Delete up to `limit` events published before `cutoff`. Returns number of deleted events.
+#[instrument(skip_all)]
+pub async fn delete_published(
+    pool: &DbPool,
+    cutoff: NaiveDateTime,
+    limit: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = query!(
+        "DELETE FROM event_outbox WHERE id IN (SELECT id FROM event_outbox \
+        WHERE published_at < $1 LIMIT $2)",
+        cutoff,
+        limit
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Publish pending events periodically, every `event_poll_interval` seconds.
+pub async fn run_publish_task(pool: DbPool, config: Config, sink: Box<dyn EventSink>) {
+    let mut interval = actix_web::rt::time::interval(StdDuration::from_secs(
+        config.event_poll_interval.max(1).into(),
+    ));
+    loop {
+        interval.tick().await;
+        if let Err(err) = publish_pending(&pool, sink.as_ref()).await {
+            log::error!("Event publishing failed: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_envelope() {
+        let event = AuthEvent::SessionRevoked {
+            address: "0x01".into(),
+            session_id: Some(1),
+            count: 2,
+        };
+        let payload: serde_json::Value =
+            serde_json::from_str(&EventEnvelope::new(&event).to_json()).unwrap();
+        assert_eq!(payload["version"], EVENT_VERSION);
+        assert!(payload["id"].is_string());
+        assert_eq!(payload["event"], event.name());
+        assert_eq!(payload["data"]["address"], "0x01");
+        assert_eq!(payload["data"]["count"], 2);
+    }
+
+    #[cfg(feature = "kafka")]
+    #[test]
+    fn test_partition_index() {
+        assert_eq!(partition_index("0x01", 1), 0);
+        let index = partition_index("0x01", 8);
+        assert!(index < 8);
+        assert_eq!(partition_index("0x01", 8), index);
+        let indexes: std::collections::HashSet<_> = (0..100)
+            .map(|i| partition_index(&format!("0x{i:02x}"), 8))
+            .collect();
+        assert!(indexes.len() > 1);
+    }
+}
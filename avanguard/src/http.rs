@@ -1,86 +1,812 @@
+use std::{
+    collections::BTreeMap,
+    future::{ready, Ready},
+    time::{Duration as StdDuration, UNIX_EPOCH},
+};
+
 use actix_web::{
-    get, post,
-    web::{self, Json},
+    body::MessageBody,
+    delete,
+    dev::{Payload, Service, ServiceResponse},
+    error::{JsonPayloadError, PathError, QueryPayloadError},
+    get,
+    http::header::{self, HeaderName, HeaderValue, HttpDate, TryIntoHeaderValue},
+    post,
+    web::{self, Json, Path, Query},
+    FromRequest, HttpRequest, HttpResponse,
 };
-use chrono::{Duration, Utc};
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Utc};
+use futures_util::future::LocalBoxFuture;
 use openidconnect::{
     core::{
-        CoreGenderClaim, CoreHmacKey, CoreIdToken, CoreIdTokenClaims, CoreJsonWebKeyType,
-        CoreJweContentEncryptionAlgorithm, CoreJwsSigningAlgorithm, CoreRsaPrivateSigningKey,
+        CoreGenderClaim, CoreHmacKey, CoreIdToken, CoreIdTokenVerifier, CoreJsonWebKeySet,
+        CoreJsonWebKeyType, CoreJweContentEncryptionAlgorithm, CoreJwsSigningAlgorithm,
+        CoreRsaPrivateSigningKey,
     },
     url::Url,
-    Audience, EmptyAdditionalClaims, IdToken, IssuerUrl, JsonWebTokenError, Nonce, StandardClaims,
-    SubjectIdentifier,
+    Audience, ClientId, ClientSecret, IdToken, IdTokenClaims, IssuerUrl, JsonWebTokenError, Nonce,
+    StandardClaims, SubjectIdentifier,
 };
-use sqlx::query_as;
+use prometheus::TEXT_FORMAT;
+use sqlx::{query, PgExecutor, Postgres, Transaction};
+use tracing::instrument;
+use utoipa::ToSchema;
 
 use crate::{
-    db::{RefreshToken, Wallet},
-    error::ApiError,
+    audit::AuditEvent,
+    claims::{collect_claims, ClaimsRequest, ExtraClaims},
+    crypto::constant_time_eq,
+    db::{
+        is_unique_violation, pending_migrations, Group, LoginEvent, RefreshToken, Session, Wallet,
+    },
+    error::{ApiError, OAuthError, Web3Error},
+    events::{emit, AuthEvent},
+    lockout::LockoutPolicy,
+    metrics::{Metrics, SUCCESS},
+    openapi::openapi_json,
+    server::ClientCertificate,
     state::AppState,
+    webhook::Webhook,
+    Config,
 };
 
-#[derive(Serialize, Deserialize)]
+/// Path prefix of version 1 of the API.
+pub const API_V1: &str = "/api/v1";
+
+/// `Deprecation` header of unversioned paths, date versioning was introduced (2026-10-19)
+/// in RFC 9745 format.
+const LEGACY_API_DEPRECATION: &str = "@1792368000";
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Challenge {
+    /// EIP-712 typed data to sign, serialized as JSON.
     pub challenge: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct WalletAddress {
+    /// Hex-encoded wallet address with `0x` prefix.
+    #[schema(example = "0x8aff0a12f3e8d55cc718d36f84e002c335df2f4a")]
     pub address: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct WalletSignature {
+    /// Hex-encoded wallet address with `0x` prefix.
     pub address: String,
+    /// Hex-encoded signature of the challenge.
     pub signature: String,
+    /// Nonce included in the id token.
     pub nonce: String,
+    /// Registered client the token is issued to, the configured client if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Space-delimited scopes, passed to claims providers at login and refresh.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    #[schema(example = "openid groups")]
+    pub scope: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct JwtToken {
+    /// OIDC id token signed with client secret.
     pub token: String,
+    /// Single-use token to obtain new tokens at `/refresh`.
     pub refresh_token: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct RefreshTokenRequest {
     pub refresh_token: String,
 }
 
+/// Default number of login history events returned at once.
+const DEFAULT_PAGE_SIZE: i64 = 100;
+
+/// Maximum number of login history events returned at once.
+const MAX_PAGE_SIZE: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct Pagination {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookRequest {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct GroupRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct GroupMemberRequest {
+    pub address: String,
+}
+
+/// Created webhook together with its signing secret, which is not returned afterwards.
+#[derive(Serialize)]
+pub struct WebhookCreated {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+/// Extractor guarding admin API, requires `Authorization: Bearer <admin token>` header
+/// and, if `admin_client_ca` is configured, verified TLS client certificate.
+pub struct AdminAuth;
+
+impl FromRequest for AdminAuth {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let Some(app_state) = req.app_data::<web::Data<AppState>>() else {
+            return ready(Err(ApiError::Unauthorized));
+        };
+        let Some(admin_token) = app_state.secrets().admin_token else {
+            log::warn!("Admin API request rejected, admin token is not configured");
+            return ready(Err(ApiError::Unauthorized));
+        };
+        if app_state.config.admin_client_ca.is_some()
+            && req.conn_data::<ClientCertificate>().is_none()
+        {
+            log::warn!("Admin API request rejected, no TLS client certificate");
+            return ready(Err(ApiError::Unauthorized));
+        }
+        let authorized = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(token.as_bytes(), admin_token.as_bytes()));
+        ready(if authorized {
+            Ok(Self)
+        } else {
+            Err(ApiError::Unauthorized)
+        })
+    }
+}
+
+/// Extractor authenticating wallet owner with `Authorization: Bearer <id token>` header.
+pub struct UserAuth {
+    pub address: String,
+}
+
+impl UserAuth {
+    async fn verify(req: HttpRequest) -> Result<Self, ApiError> {
+        let app_state = req
+            .app_data::<web::Data<AppState>>()
+            .ok_or(ApiError::Unauthorized)?;
+        let id_token: CoreIdToken = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| token.parse().ok())
+            .ok_or(ApiError::Unauthorized)?;
+        // Audience selects the client whose secret the token is signed with
+        let client_id = id_token
+            .claims(
+                &CoreIdTokenVerifier::new_insecure_without_verification(),
+                |_: Option<&Nonce>| Ok(()),
+            )
+            .ok()
+            .and_then(|claims| {
+                claims
+                    .audiences()
+                    .first()
+                    .map(|audience| audience.to_string())
+            })
+            .ok_or(ApiError::Unauthorized)?;
+        let client = app_state
+            .client_credentials(Some(&client_id))
+            .await?
+            .ok_or(ApiError::Unauthorized)?;
+        let verifier = CoreIdTokenVerifier::new_confidential_client(
+            ClientId::new(client.client_id),
+            ClientSecret::new(client.secret),
+            IssuerUrl::from_url(app_state.config.issuer_url.clone()),
+            CoreJsonWebKeySet::default(),
+        )
+        .set_allowed_algs([CoreJwsSigningAlgorithm::HmacSha256]);
+        // Nonce is only meaningful to the client which requested the token
+        let claims = id_token
+            .claims(&verifier, |_: Option<&Nonce>| Ok(()))
+            .map_err(|err| {
+                log::debug!("Rejected id token: {err}");
+                ApiError::Unauthorized
+            })?;
+        Ok(Self {
+            address: claims.subject().to_string(),
+        })
+    }
+}
+
+impl FromRequest for UserAuth {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        Box::pin(Self::verify(req.clone()))
+    }
+}
+
+/// Metrics in Prometheus text format.
+#[get("/metrics")]
+async fn metrics(app_state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(TEXT_FORMAT)
+        .body(app_state.metrics.render(&app_state.pool))
+}
+
 /// Simple HTTP server health check.
-#[get("/api/health")]
+#[get("/health")]
 async fn health_check() -> &'static str {
     "alive"
 }
 
-// List wallets
-#[get("/api/wallet")]
-async fn list_wallets(app_state: web::Data<AppState>) -> Result<Json<Vec<Wallet>>, ApiError> {
-    let wallets = query_as!(
-        Wallet,
-        "SELECT id \"id?\", address, challenge_message, challenge_signature, creation_timestamp, validation_timestamp FROM wallet"
-    ).fetch_all(&app_state.pool).await?;
+/// Liveness probe, succeeds as long as the server is able to handle requests.
+#[get("/health/live")]
+async fn health_live() -> &'static str {
+    "alive"
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    /// Result of every check, `ok` or failure reason.
+    checks: BTreeMap<&'static str, String>,
+}
+
+/// Readiness probe, checks database connectivity, applied migrations and signing key.
+/// Returns 503 if the service can't serve requests.
+#[get("/health/ready")]
+async fn health_ready(app_state: web::Data<AppState>) -> HttpResponse {
+    let mut checks = BTreeMap::new();
+    let database = match query("SELECT 1").execute(&app_state.pool).await {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    };
+    let migrations = match &database {
+        Ok(()) => match pending_migrations(&app_state.pool).await {
+            Ok(0) => Ok(()),
+            Ok(pending) => Err(format!("{pending} pending migrations")),
+            Err(err) => Err(err.to_string()),
+        },
+        Err(_) => Err("database unavailable".into()),
+    };
+    let signing_key = if app_state.secrets().client_secret.is_empty() {
+        Err("client secret not set".into())
+    } else {
+        Ok(())
+    };
+    let mut ready = true;
+    for (name, check) in [
+        ("database", database),
+        ("migrations", migrations),
+        ("signing_key", signing_key),
+    ] {
+        let status = match check {
+            Ok(()) => "ok".into(),
+            Err(err) => {
+                log::warn!("Readiness check {name} failed: {err}");
+                ready = false;
+                err
+            }
+        };
+        checks.insert(name, status);
+    }
+    let mut response = if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    response.json(Readiness { ready, checks })
+}
+
+/// List wallets.
+#[get("/wallet")]
+#[instrument(skip_all)]
+async fn list_wallets(
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+) -> Result<Json<Vec<Wallet>>, ApiError> {
+    let wallets = Wallet::all(&app_state.pool).await?;
     Ok(Json(wallets))
 }
 
+/// Clear failed login attempts and lift the lockout of a wallet.
+#[delete("/wallet/{address}/lockout")]
+#[instrument(skip_all, fields(address = %address))]
+async fn clear_wallet_lockout(
+    req: HttpRequest,
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    address: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let address = address.into_inner().to_lowercase();
+    let Some(mut wallet) = Wallet::find_by_address(&app_state.pool, &address).await? else {
+        return Err(ApiError::WalletNotFound);
+    };
+    wallet.clear_failed_logins(&app_state.pool).await?;
+    log::info!("Cleared lockout of wallet {address}");
+    app_state.audit.log(
+        &req,
+        &app_state,
+        Some(&address),
+        AuditEvent::AdminAction {
+            action: String::from("clear_lockout"),
+        },
+    );
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn find_wallet_id(app_state: &AppState, address: &str) -> Result<i64, ApiError> {
+    Wallet::find_by_address(&app_state.pool, &address.to_lowercase())
+        .await?
+        .and_then(|wallet| wallet.id)
+        .ok_or(ApiError::WalletNotFound)
+}
+
+/// Login history of a wallet, most recent first.
+#[get("/wallet/{address}/logins")]
+#[instrument(skip_all, fields(address = %address))]
+async fn list_wallet_logins(
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    address: Path<String>,
+    pagination: Query<Pagination>,
+) -> Result<Json<Vec<LoginEvent>>, ApiError> {
+    let wallet_id = find_wallet_id(&app_state, &address).await?;
+    let limit = pagination
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = pagination.offset.unwrap_or_default().max(0);
+    let events = LoginEvent::find_by_wallet(&app_state.pool, wallet_id, limit, offset).await?;
+    Ok(Json(events))
+}
+
+/// Active sessions of a wallet, i.e. logins with refresh token which can still be used.
+#[get("/wallet/{address}/sessions")]
+#[instrument(skip_all, fields(address = %address))]
+async fn list_wallet_sessions(
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    address: Path<String>,
+) -> Result<Json<Vec<Session>>, ApiError> {
+    let wallet_id = find_wallet_id(&app_state, &address).await?;
+    let sessions = Session::find_by_wallet(&app_state.pool, wallet_id).await?;
+    Ok(Json(sessions))
+}
+
+/// List webhook subscriptions.
+#[get("/webhook")]
+#[instrument(skip_all)]
+async fn list_webhooks(
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+) -> Result<Json<Vec<Webhook>>, ApiError> {
+    let webhooks = Webhook::all(&app_state.pool).await?;
+    Ok(Json(webhooks))
+}
+
+/// Subscribe webhook to events. Empty event list subscribes to all events.
+#[post("/webhook")]
+#[instrument(skip_all, fields(url = %data.url))]
+async fn create_webhook(
+    req: HttpRequest,
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    data: Json<WebhookRequest>,
+) -> Result<Json<WebhookCreated>, ApiError> {
+    let data = data.into_inner();
+    if !Url::parse(&data.url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+        return Err(ApiError::InvalidRequest(format!(
+            "Invalid webhook URL {}",
+            data.url
+        )));
+    }
+    if let Some(event) = data
+        .events
+        .iter()
+        .find(|event| !AuthEvent::NAMES.contains(&event.as_str()))
+    {
+        return Err(ApiError::InvalidRequest(format!("Unknown event {event}")));
+    }
+    let mut webhook = Webhook::new(data.url, data.events);
+    webhook.save(&app_state.pool).await?;
+    log::info!("Created webhook {:?} for {}", webhook.id, webhook.url);
+    app_state.audit.log(
+        &req,
+        &app_state,
+        None,
+        AuditEvent::AdminAction {
+            action: format!("create_webhook {}", webhook.url),
+        },
+    );
+    let secret = webhook.secret.clone();
+    Ok(Json(WebhookCreated { webhook, secret }))
+}
+
+/// Delete webhook subscription together with its pending deliveries.
+#[delete("/webhook/{id}")]
+#[instrument(skip_all, fields(id = %id))]
+async fn delete_webhook(
+    req: HttpRequest,
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    id: Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let Some(webhook) = Webhook::find_by_id(&app_state.pool, *id).await? else {
+        return Err(ApiError::WebhookNotFound);
+    };
+    let url = webhook.url.clone();
+    webhook.delete(&app_state.pool).await?;
+    log::info!("Deleted webhook {id} for {url}");
+    app_state.audit.log(
+        &req,
+        &app_state,
+        None,
+        AuditEvent::AdminAction {
+            action: format!("delete_webhook {url}"),
+        },
+    );
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn find_group(app_state: &AppState, name: &str) -> Result<Group, ApiError> {
+    Group::find_by_name(&app_state.pool, name)
+        .await?
+        .ok_or(ApiError::GroupNotFound)
+}
+
+/// Record group change made with admin API in audit log.
+fn audit_group_action(
+    req: &HttpRequest,
+    app_state: &AppState,
+    address: Option<&str>,
+    action: String,
+) {
+    app_state
+        .audit
+        .log(req, app_state, address, AuditEvent::AdminAction { action });
+}
+
+/// List groups.
+#[get("/group")]
+#[instrument(skip_all)]
+async fn list_groups(
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+) -> Result<Json<Vec<Group>>, ApiError> {
+    let groups = Group::all(&app_state.pool).await?;
+    Ok(Json(groups))
+}
+
+/// Create group.
+#[post("/group")]
+#[instrument(skip_all, fields(name = %data.name))]
+async fn create_group(
+    req: HttpRequest,
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    data: Json<GroupRequest>,
+) -> Result<Json<Group>, ApiError> {
+    let name = data.into_inner().name;
+    if !Group::is_valid_name(&name) {
+        return Err(ApiError::InvalidRequest(format!(
+            "Invalid group name {name:?}"
+        )));
+    }
+    let mut group = Group::new(name);
+    group.save(&app_state.pool).await.map_err(|err| match err {
+        err if is_unique_violation(&err) => ApiError::GroupExists,
+        err => err.into(),
+    })?;
+    log::info!("Created group {}", group.name);
+    audit_group_action(
+        &req,
+        &app_state,
+        None,
+        format!("create_group {}", group.name),
+    );
+    Ok(Json(group))
+}
+
+/// Delete group, removing its wallets from it.
+#[delete("/group/{name}")]
+#[instrument(skip_all, fields(name = %name))]
+async fn delete_group(
+    req: HttpRequest,
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    name: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let group = find_group(&app_state, &name).await?;
+    group.delete(&app_state.pool).await?;
+    log::info!("Deleted group {name}");
+    audit_group_action(&req, &app_state, None, format!("delete_group {name}"));
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Addresses of wallets in a group.
+#[get("/group/{name}/wallets")]
+#[instrument(skip_all, fields(name = %name))]
+async fn list_group_wallets(
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    name: Path<String>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let group = find_group(&app_state, &name).await?;
+    let members = group.members(&app_state.pool).await?;
+    Ok(Json(members))
+}
+
+/// Add wallet to a group.
+#[post("/group/{name}/wallets")]
+#[instrument(skip_all, fields(name = %name, address = %data.address))]
+async fn add_group_wallet(
+    req: HttpRequest,
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    name: Path<String>,
+    data: Json<GroupMemberRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let group = find_group(&app_state, &name).await?;
+    let wallet_id = find_wallet_id(&app_state, &data.address).await?;
+    let address = data.address.to_lowercase();
+    if group.add_wallet(&app_state.pool, wallet_id).await? {
+        log::info!("Added wallet {address} to group {name}");
+        audit_group_action(
+            &req,
+            &app_state,
+            Some(&address),
+            format!("add_to_group {name}"),
+        );
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Remove wallet from a group.
+#[delete("/group/{name}/wallets/{address}")]
+#[instrument(skip_all, fields(name = %path.0, address = %path.1))]
+async fn remove_group_wallet(
+    req: HttpRequest,
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    path: Path<(String, String)>,
+) -> Result<HttpResponse, ApiError> {
+    let (name, address) = path.into_inner();
+    let group = find_group(&app_state, &name).await?;
+    let wallet_id = find_wallet_id(&app_state, &address).await?;
+    if !group.remove_wallet(&app_state.pool, wallet_id).await? {
+        return Err(ApiError::NotGroupMember);
+    }
+    let address = address.to_lowercase();
+    log::info!("Removed wallet {address} from group {name}");
+    audit_group_action(
+        &req,
+        &app_state,
+        Some(&address),
+        format!("remove_from_group {name}"),
+    );
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Names of groups a wallet belongs to.
+#[get("/wallet/{address}/groups")]
+#[instrument(skip_all, fields(address = %address))]
+async fn list_wallet_groups(
+    _admin: AdminAuth,
+    app_state: web::Data<AppState>,
+    address: Path<String>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let wallet_id = find_wallet_id(&app_state, &address).await?;
+    let groups = Group::names_by_wallet(&app_state.pool, wallet_id).await?;
+    Ok(Json(groups))
+}
+
+/// Active sessions of the authenticated wallet owner.
+#[utoipa::path(
+    tag = "session",
+    security(("id_token" = [])),
+    responses(
+        (status = 200, description = "Active sessions, most recent first", body = [Session]),
+        (status = 401, description = "Missing or invalid id token", body = ErrorInfo),
+    )
+)]
+#[get("/session")]
+#[instrument(skip_all, fields(address = %user.address))]
+async fn list_sessions(
+    user: UserAuth,
+    app_state: web::Data<AppState>,
+) -> Result<Json<Vec<Session>>, ApiError> {
+    let wallet_id = find_wallet_id(&app_state, &user.address).await?;
+    let sessions = Session::find_by_wallet(&app_state.pool, wallet_id).await?;
+    Ok(Json(sessions))
+}
+
+/// Record revocation of `count` refresh tokens of session `session_id`, or all sessions if `None`.
+/// Commits the transaction the tokens were revoked in.
+async fn record_revocation(
+    req: &HttpRequest,
+    app_state: &AppState,
+    mut transaction: Transaction<'_, Postgres>,
+    address: &str,
+    session_id: Option<i64>,
+    count: u64,
+) -> Result<(), ApiError> {
+    let event = AuthEvent::SessionRevoked {
+        address: address.into(),
+        session_id,
+        count,
+    };
+    if count > 0 {
+        emit(&mut transaction, &app_state.config, &event).await?;
+    }
+    transaction.commit().await?;
+    if count > 0 {
+        app_state.notify(&event);
+    }
+    app_state.metrics.revocations.inc_by(count);
+    app_state.audit.log(
+        req,
+        app_state,
+        Some(address),
+        AuditEvent::TokenRevoked { count },
+    );
+    Ok(())
+}
+
+/// Revoke session of the authenticated wallet owner, identified by its login event id.
+#[utoipa::path(
+    tag = "session",
+    security(("id_token" = [])),
+    params(("id" = i64, Path, description = "Login event id of the session")),
+    responses(
+        (status = 204, description = "Session revoked"),
+        (status = 401, description = "Missing or invalid id token", body = ErrorInfo),
+        (status = 404, description = "Session not found", body = ErrorInfo),
+    )
+)]
+#[delete("/session/{id}")]
+#[instrument(skip_all, fields(address = %user.address, id = %id))]
+async fn revoke_session(
+    req: HttpRequest,
+    user: UserAuth,
+    app_state: web::Data<AppState>,
+    id: Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = find_wallet_id(&app_state, &user.address).await?;
+    let mut transaction = app_state.pool.begin().await?;
+    let count = RefreshToken::blacklist_family(&mut *transaction, wallet_id, *id).await?;
+    if count == 0 {
+        return Err(ApiError::SessionNotFound);
+    }
+    record_revocation(
+        &req,
+        &app_state,
+        transaction,
+        &user.address,
+        Some(*id),
+        count,
+    )
+    .await?;
+    log::info!("Revoked session {id} of wallet {}", user.address);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Log the authenticated wallet owner out everywhere by blacklisting all their refresh tokens.
+#[utoipa::path(
+    tag = "session",
+    security(("id_token" = [])),
+    responses(
+        (status = 204, description = "All sessions revoked"),
+        (status = 401, description = "Missing or invalid id token", body = ErrorInfo),
+    )
+)]
+#[delete("/session")]
+#[instrument(skip_all, fields(address = %user.address))]
+async fn revoke_all_sessions(
+    req: HttpRequest,
+    user: UserAuth,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet_id = find_wallet_id(&app_state, &user.address).await?;
+    let mut transaction = app_state.pool.begin().await?;
+    let count = RefreshToken::blacklist_wallet(&mut *transaction, wallet_id).await?;
+    record_revocation(&req, &app_state, transaction, &user.address, None, count).await?;
+    log::info!("Revoked all sessions of wallet {}", user.address);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Record login attempt in wallet login history.
+async fn record_login<'e, E: PgExecutor<'e>>(
+    executor: E,
+    req: &HttpRequest,
+    app_state: &AppState,
+    wallet_id: i64,
+    client_id: &str,
+    outcome: &str,
+) -> Result<LoginEvent, ApiError> {
+    let mut login_event = LoginEvent::new(
+        wallet_id,
+        app_state
+            .rate_limiter
+            .client_ip(req)
+            .map(|ip| ip.to_string()),
+        req.headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from),
+        client_id.into(),
+        LoginEvent::METHOD_EIP712,
+        outcome,
+    );
+    login_event.save(executor).await?;
+    Ok(login_event)
+}
+
 /// Start Web3 authentication. Returns challenge message for specified wallet address.
+#[utoipa::path(
+    tag = "auth",
+    request_body = WalletAddress,
+    responses(
+        (status = 200, description = "Challenge to sign with the wallet", body = Challenge),
+        (status = 429, description = "Rate limit exceeded", body = ErrorInfo),
+    )
+)]
 #[post("/auth/start")]
 pub async fn web3auth_start(
+    req: HttpRequest,
     app_state: web::Data<AppState>,
     data: Json<WalletAddress>,
 ) -> Result<Json<Challenge>, ApiError> {
+    let result = start_auth(&req, &app_state, data.into_inner()).await;
+    Metrics::count(&app_state.metrics.challenges, &result);
+    result
+}
+
+#[instrument(skip_all, fields(address = %data.address))]
+async fn start_auth(
+    req: &HttpRequest,
+    app_state: &AppState,
+    data: WalletAddress,
+) -> Result<Json<Challenge>, ApiError> {
+    let address = data.address.to_lowercase();
+    app_state.rate_limiter.check_ip("auth_start", req).await?;
+    app_state
+        .rate_limiter
+        .check_address("auth_start", &address)
+        .await?;
     // Create wallet if it does not exist yet
-    let address = data.into_inner().address.to_lowercase();
-    let mut wallet =
-        if let Some(wallet) = Wallet::find_by_address(&app_state.pool, &address).await? {
-            wallet
-        } else {
-            let mut wallet = Wallet::new(address);
-            wallet.save(&app_state.pool).await?;
-            wallet
+    let wallet = if let Some(wallet) = Wallet::find_by_address(&app_state.pool, &address).await? {
+        wallet
+    } else {
+        let mut transaction = app_state.pool.begin().await?;
+        let mut wallet = Wallet::with_template(address, &app_state.challenge_template());
+        wallet.save(&mut *transaction).await?;
+        let event = AuthEvent::WalletRegistered {
+            address: wallet.address.clone(),
         };
-    wallet.save(&app_state.pool).await?;
+        emit(&mut transaction, &app_state.config, &event).await?;
+        transaction.commit().await?;
+        app_state.notify(&event);
+        wallet
+    };
+    app_state.audit.log(
+        req,
+        app_state,
+        Some(&wallet.address),
+        AuditEvent::ChallengeIssued,
+    );
     Ok(Json(Challenge {
         challenge: wallet.challenge_message,
     }))
@@ -89,15 +815,15 @@ pub async fn web3auth_start(
 /// Creates OIDC id token for given wallet
 fn issue_id_token<T>(
     wallet_address: &str,
-    base_url: &Url,
+    config: &Config,
     secret: T,
     rsa_key: Option<CoreRsaPrivateSigningKey>,
     nonce: &str,
     client_id: &str,
-    token_expiration: u32,
+    extra_claims: ExtraClaims,
 ) -> Result<
     IdToken<
-        EmptyAdditionalClaims,
+        ExtraClaims,
         CoreGenderClaim,
         CoreJweContentEncryptionAlgorithm,
         CoreJwsSigningAlgorithm,
@@ -110,20 +836,20 @@ where
 {
     let wallet_address = wallet_address.to_lowercase();
     let issue_time = Utc::now();
-    let expiration = issue_time + Duration::seconds(token_expiration.into());
+    let expiration = issue_time + Duration::seconds(config.token_timeout.into());
     let claims = StandardClaims::new(SubjectIdentifier::new(wallet_address));
-    let id_token_claims = CoreIdTokenClaims::new(
-        IssuerUrl::from_url(base_url.clone()),
+    let id_token_claims = IdTokenClaims::new(
+        IssuerUrl::from_url(config.issuer_url.clone()),
         vec![Audience::new(client_id.to_string())],
         expiration,
         issue_time,
         claims,
-        openidconnect::EmptyAdditionalClaims {},
+        extra_claims,
     )
     .set_nonce(Some(Nonce::new(nonce.to_string())));
     match rsa_key {
         // RSA flow
-        Some(key) => CoreIdToken::new(
+        Some(key) => IdToken::new(
             id_token_claims,
             &key,
             CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
@@ -131,7 +857,7 @@ where
             None,
         ),
         // HMAC flow
-        None => CoreIdToken::new(
+        None => IdToken::new(
             id_token_claims,
             &CoreHmacKey::new(secret),
             CoreJwsSigningAlgorithm::HmacSha256,
@@ -142,104 +868,495 @@ where
 }
 
 /// Finish Web3 authentication. Verifies signature and returns OIDC id_token if correct.
+#[utoipa::path(
+    tag = "auth",
+    request_body = WalletSignature,
+    responses(
+        (status = 200, description = "Id token and refresh token", body = JwtToken),
+        (status = 400, description = "`MalformedSignature`, `UnsupportedSignatureFormat`, \
+            `InvalidAddress` or `InvalidRequest` for malformed body", body = ErrorInfo),
+        (status = 401, description = "`SignerMismatch` if signed by another wallet, \
+            `SignatureIncorrect`, `WalletNotFound` or `ClientNotFound`", body = ErrorInfo),
+        (status = 403, description = "Wallet disabled", body = ErrorInfo),
+        (status = 429, description = "Rate limit exceeded or wallet locked", body = ErrorInfo),
+    )
+)]
 #[post("/auth")]
 pub async fn web3auth_end(
+    req: HttpRequest,
     app_state: web::Data<AppState>,
     signature: Json<WalletSignature>,
 ) -> Result<Json<JwtToken>, ApiError> {
     let address = signature.address.to_lowercase();
-    let Some(mut wallet) = Wallet::find_by_address(&app_state.pool, &address).await? else { return Err(ApiError::WalletNotFound) };
-    match wallet.verify_address(&wallet.challenge_message, &signature.signature) {
-        Ok(true) => {
+    let result = finish_auth(&req, &app_state, signature.into_inner()).await;
+    Metrics::count(&app_state.metrics.logins, &result);
+    let event = match &result {
+        Ok(_) => AuditEvent::LoginSucceeded,
+        Err(err) => AuditEvent::LoginFailed {
+            reason: err.code().into(),
+        },
+    };
+    app_state.audit.log(&req, &app_state, Some(&address), event);
+    result
+}
+
+#[instrument(skip_all, fields(address = %signature.address))]
+async fn finish_auth(
+    req: &HttpRequest,
+    app_state: &AppState,
+    signature: WalletSignature,
+) -> Result<Json<JwtToken>, ApiError> {
+    let address = signature.address.to_lowercase();
+    app_state.rate_limiter.check_ip("auth", req).await?;
+    app_state
+        .rate_limiter
+        .check_address("auth", &address)
+        .await?;
+    let client_ip = app_state.rate_limiter.client_ip(req);
+    if let Some(ip) = client_ip {
+        if let Some(locked_until) = LockoutPolicy::ip_locked_until(&app_state.pool, ip).await? {
+            let retry_after = (locked_until - Utc::now().naive_utc()).num_seconds();
+            return Err(ApiError::RateLimited(retry_after.max(1) as u64));
+        }
+    }
+    let Some(client) = app_state
+        .client_credentials(signature.client_id.as_deref())
+        .await?
+    else {
+        return Err(ApiError::ClientNotFound);
+    };
+    let Some(mut wallet) = Wallet::find_by_address(&app_state.pool, &address).await? else {
+        return Err(ApiError::WalletNotFound);
+    };
+    let Some(wallet_id) = wallet.id else {
+        log::error!("Wallet with address: {} has no id", wallet.address);
+        return Err(ApiError::WalletNotFound);
+    };
+    let client_id = client.client_id.as_str();
+    if wallet.disabled_at.is_some() {
+        let err = ApiError::WalletDisabled;
+        record_login(
+            &app_state.pool,
+            req,
+            app_state,
+            wallet_id,
+            client_id,
+            err.code(),
+        )
+        .await?;
+        return Err(err);
+    }
+    if let Some(locked_until) = wallet.locked_until() {
+        let err = ApiError::WalletLocked(locked_until);
+        record_login(
+            &app_state.pool,
+            req,
+            app_state,
+            wallet_id,
+            client_id,
+            err.code(),
+        )
+        .await?;
+        return Err(err);
+    }
+    let timer = app_state.metrics.signature_verification.start_timer();
+    let verification = wallet.validate_signature(&signature.signature);
+    timer.observe_duration();
+    match verification {
+        Ok(()) => {
+            if wallet.failed_login_attempts > 0 {
+                wallet.clear_failed_logins(&app_state.pool).await?;
+            }
+            let scopes: Vec<&str> = signature.scope.split_whitespace().collect();
+            let claims = collect_claims(
+                &app_state.claims_providers,
+                &ClaimsRequest {
+                    wallet: &wallet,
+                    client_id,
+                    scopes: &scopes,
+                },
+            )
+            .await?;
             let id_token = issue_id_token(
                 &address,
-                &app_state.config.issuer_url,
-                app_state.config.client_secret.clone(),
+                &app_state.config,
+                client.secret.as_str(),
                 None,
                 &signature.nonce,
-                &app_state.config.client_id,
-                app_state.config.token_timeout,
+                client_id,
+                claims,
             )?;
-            wallet.challenge_signature = Some(signature.signature.clone());
-            wallet.save(&app_state.pool).await?;
-            if let Some(wallet_id) = wallet.id {
-                let mut refresh_token =
-                    RefreshToken::new(wallet_id, app_state.config.refresh_token_timeout);
-                refresh_token.save(&app_state.pool).await?;
-                Ok(Json(JwtToken {
-                    token: id_token.to_string(),
-                    refresh_token: refresh_token.token,
-                }))
-            } else {
-                log::error!("Wallet with address: {} has no id", wallet.address);
-                Err(ApiError::WalletNotFound)
+            let mut transaction = app_state.pool.begin().await?;
+            wallet
+                .set_signature(&mut *transaction, &signature.signature)
+                .await?;
+            let login_event = record_login(
+                &mut *transaction,
+                req,
+                app_state,
+                wallet_id,
+                client_id,
+                SUCCESS,
+            )
+            .await?;
+            let (mut refresh_token, token) = RefreshToken::new(
+                wallet_id,
+                login_event.id,
+                app_state.config.refresh_token_timeout,
+                app_state.secrets().refresh_token_secret.as_bytes(),
+            );
+            refresh_token.scope = scopes.join(" ");
+            refresh_token.save(&mut *transaction).await?;
+            let event = AuthEvent::LoginSucceeded {
+                address,
+                session_id: login_event.id,
+            };
+            emit(&mut transaction, &app_state.config, &event).await?;
+            transaction.commit().await?;
+            app_state.notify(&event);
+            Ok(Json(JwtToken {
+                token: id_token.to_string(),
+                refresh_token: token,
+            }))
+        }
+        Err(err) => {
+            // Only signatures which don't match the wallet count towards lockout, malformed ones
+            // come from broken clients rather than from guessing
+            if matches!(err, Web3Error::Recovery | Web3Error::VerifyAddress) {
+                let policy = &app_state.lockout_policy;
+                wallet
+                    .register_failed_login(&app_state.pool, policy)
+                    .await?;
+                if let Some(locked_until) = wallet.locked_until() {
+                    app_state.audit.log(
+                        req,
+                        app_state,
+                        Some(&address),
+                        AuditEvent::WalletLocked { locked_until },
+                    );
+                }
+                if let Some(ip) = client_ip {
+                    policy.register_ip_failure(&app_state.pool, ip).await?;
+                }
             }
+            let err = ApiError::from(err);
+            record_login(
+                &app_state.pool,
+                req,
+                app_state,
+                wallet_id,
+                client_id,
+                err.code(),
+            )
+            .await?;
+            Err(err)
         }
-        _ => Err(ApiError::SignatureIncorrect),
     }
 }
 
-/// Issue new id token and refresh token set old as used
+/// Issue new id token and refresh token set old as used. Errors are returned in OAuth 2.0 format
+/// as for refresh token grant.
+#[utoipa::path(
+    tag = "auth",
+    request_body = RefreshTokenRequest,
+    responses(
+        (status = 200, description = "New id token and refresh token", body = JwtToken),
+        (status = 400, description = "`invalid_grant` if refresh token isn't found, is used or expired, \
+            or the wallet is disabled; `invalid_request` for malformed body", body = OAuthErrorInfo),
+        (status = 401, description = "`invalid_client` if the client was removed", body = OAuthErrorInfo),
+    )
+)]
 #[post("/refresh")]
 pub async fn refresh(
+    req: HttpRequest,
     app_state: web::Data<AppState>,
-    data: Json<RefreshTokenRequest>,
+    // Extraction errors are returned in OAuth format too
+    data: Result<Json<RefreshTokenRequest>, actix_web::Error>,
+) -> Result<Json<JwtToken>, OAuthError> {
+    let data = data.map_err(|err| match err.as_error::<ApiError>() {
+        Some(ApiError::InvalidRequest(message)) => ApiError::InvalidRequest(message.clone()),
+        _ => ApiError::InvalidRequest(err.to_string()),
+    })?;
+    let result = refresh_token(&req, &app_state, data.into_inner()).await;
+    Metrics::count(&app_state.metrics.refreshes, &result);
+    Ok(result?)
+}
+
+#[instrument(skip_all)]
+async fn refresh_token(
+    req: &HttpRequest,
+    app_state: &AppState,
+    data: RefreshTokenRequest,
 ) -> Result<Json<JwtToken>, ApiError> {
-    let refresh_token = data.into_inner().refresh_token;
-    if let Ok(Some(mut refresh_token)) =
-        RefreshToken::find_refresh_token(&app_state.pool, &refresh_token).await
-    {
+    app_state.rate_limiter.check_ip("refresh", req).await?;
+    let secrets = app_state.refresh_token_secrets();
+    let mut found = None;
+    for secret in &secrets {
+        if let Some(refresh_token) = RefreshToken::find_refresh_token(
+            &app_state.pool,
+            secret.as_bytes(),
+            &data.refresh_token,
+        )
+        .await?
+        {
+            found = Some(refresh_token);
+            break;
+        }
+    }
+    if let Some(mut refresh_token) = found {
         log::debug!(
-            "Refreshing token: {} for user with id: {}",
-            refresh_token.token,
+            "Refreshing token with id: {:?} for user with id: {}",
+            refresh_token.id,
             refresh_token.wallet_id,
         );
-        refresh_token.set_used(&app_state.pool).await?;
-        let mut new_refresh_token = RefreshToken::new(
+        let mut transaction = app_state.pool.begin().await?;
+        if !refresh_token.set_used(&mut *transaction).await? {
+            log::debug!("Refresh token used or revoked concurrently");
+            return Err(ApiError::TokenNotFound);
+        }
+        let (mut new_refresh_token, token) = RefreshToken::new(
             refresh_token.wallet_id,
+            refresh_token.login_event_id,
             app_state.config.refresh_token_timeout,
+            // New token is hashed with the current secret
+            secrets[0].as_bytes(),
         );
-        if let Some(wallet) = Wallet::find_by_id(&app_state.pool, refresh_token.wallet_id).await? {
+        if let Some(wallet) = Wallet::find_by_id(&mut *transaction, refresh_token.wallet_id).await?
+        {
+            app_state
+                .rate_limiter
+                .check_address("refresh", &wallet.address)
+                .await?;
+            if wallet.disabled_at.is_some() {
+                return Err(ApiError::WalletDisabled);
+            }
+            // Token is issued to the client which logged in
+            let client_id = match refresh_token.login_event_id {
+                Some(id) => LoginEvent::find_by_id(&mut *transaction, id)
+                    .await?
+                    .map(|login_event| login_event.client_id),
+                None => None,
+            };
+            let Some(client) = app_state.client_credentials(client_id.as_deref()).await? else {
+                return Err(ApiError::ClientNotFound);
+            };
             // Doesn't return nonce while refreshing token
             // https://openid.net/specs/openid-connect-core-1_0.html#RefreshTokenResponse
+            let scopes: Vec<&str> = refresh_token.scope.split_whitespace().collect();
+            let claims = collect_claims(
+                &app_state.claims_providers,
+                &ClaimsRequest {
+                    wallet: &wallet,
+                    client_id: &client.client_id,
+                    scopes: &scopes,
+                },
+            )
+            .await?;
             let id_token = issue_id_token(
                 &wallet.address,
-                &app_state.config.issuer_url,
-                app_state.config.client_secret.clone(),
+                &app_state.config,
+                client.secret.as_str(),
                 None,
                 "",
-                &app_state.config.client_id,
-                app_state.config.token_timeout,
+                &client.client_id,
+                claims,
             )?;
-            new_refresh_token.save(&app_state.pool).await?;
+            new_refresh_token.scope = refresh_token.scope.clone();
+            new_refresh_token.save(&mut *transaction).await?;
+            let event = AuthEvent::TokenRefreshed {
+                address: wallet.address.clone(),
+                session_id: refresh_token.login_event_id,
+            };
+            emit(&mut transaction, &app_state.config, &event).await?;
+            transaction.commit().await?;
+            app_state.notify(&event);
             log::info!(
                 "Issued new id_token and refresh token for user with id: {}",
                 refresh_token.wallet_id,
             );
+            app_state.audit.log(
+                req,
+                app_state,
+                Some(&wallet.address),
+                AuditEvent::TokenRefreshed,
+            );
             Ok(Json(JwtToken {
                 token: id_token.to_string(),
-                refresh_token: new_refresh_token.token,
+                refresh_token: token,
             }))
         } else {
             log::debug!(
-                "Wallet with id: {} assigned to token: {} not found",
+                "Wallet with id: {} assigned to token with id: {:?} not found",
                 refresh_token.wallet_id,
-                refresh_token.token,
+                refresh_token.id,
             );
             Err(ApiError::WalletNotFound)
         }
     } else {
-        log::debug!("Refresh token: {} not found", refresh_token);
+        log::debug!("Refresh token not found");
         Err(ApiError::TokenNotFound)
     }
 }
 
 /// Configure Actix Web server.
 pub fn config_service(config: &mut web::ServiceConfig) {
+    versioned_service(config);
+    probe_endpoints(config);
+    metrics_endpoints(config);
+    legacy_service(config);
+}
+
+/// `/api/v1` endpoints.
+pub(crate) fn versioned_service(config: &mut web::ServiceConfig) {
+    config
+        .app_data(web::JsonConfig::default().error_handler(json_error))
+        .app_data(web::PathConfig::default().error_handler(path_error))
+        .app_data(web::QueryConfig::default().error_handler(query_error))
+        .service(
+            web::scope(API_V1)
+                .configure(auth_endpoints)
+                .configure(api_endpoints),
+        );
+    #[cfg(feature = "swagger-ui")]
+    config.service(crate::openapi::swagger_ui());
+}
+
+/// Unversioned paths of v1 endpoints, registered last to match only paths not matched before.
+pub(crate) fn legacy_service(config: &mut web::ServiceConfig) {
+    config.service(
+        web::scope("")
+            .wrap_fn(|req, service| {
+                let response = service.call(req);
+                async move {
+                    let mut response = legacy_error(response.await?);
+                    deprecate(&mut response);
+                    Ok(response)
+                }
+            })
+            .configure(auth_endpoints)
+            .service(web::scope("/api").configure(api_endpoints)),
+    );
+}
+
+/// Liveness and readiness probes, which aren't versioned.
+pub(crate) fn probe_endpoints(config: &mut web::ServiceConfig) {
+    config.service(health_live).service(health_ready);
+}
+
+/// Prometheus metrics, which aren't versioned.
+pub(crate) fn metrics_endpoints(config: &mut web::ServiceConfig) {
+    config.service(metrics);
+}
+
+/// Return malformed JSON body as [`ApiError::InvalidRequest`] instead of plain text.
+fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let message = match err {
+        JsonPayloadError::ContentType => String::from("Content-Type must be application/json"),
+        JsonPayloadError::Deserialize(err) => format!("Invalid JSON body: {err}"),
+        JsonPayloadError::Overflow { limit }
+        | JsonPayloadError::OverflowKnownLength { limit, .. } => {
+            format!("Request body larger than {limit} bytes")
+        }
+        err => err.to_string(),
+    };
+    ApiError::InvalidRequest(message).into()
+}
+
+/// Return invalid path parameters as [`ApiError::InvalidRequest`].
+fn path_error(err: PathError, _req: &HttpRequest) -> actix_web::Error {
+    let PathError::Deserialize(err) = err else {
+        return ApiError::InvalidRequest(err.to_string()).into();
+    };
+    ApiError::InvalidRequest(format!("Invalid path parameter: {err}")).into()
+}
+
+/// Return invalid query parameters as [`ApiError::InvalidRequest`].
+fn query_error(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let QueryPayloadError::Deserialize(err) = err else {
+        return ApiError::InvalidRequest(err.to_string()).into();
+    };
+    ApiError::InvalidRequest(format!("Invalid query parameter: {err}")).into()
+}
+
+/// Endpoints authenticating wallet owners, at `/auth/start`, `/auth` and `/refresh` before
+/// versioning.
+fn auth_endpoints(config: &mut web::ServiceConfig) {
     config
-        .service(health_check)
-        .service(list_wallets)
         .service(web3auth_start)
         .service(web3auth_end)
         .service(refresh);
 }
+
+/// Endpoints which were under `/api` before versioning.
+fn api_endpoints(config: &mut web::ServiceConfig) {
+    config
+        .service(health_check)
+        .service(list_wallets)
+        .service(clear_wallet_lockout)
+        .service(list_wallet_logins)
+        .service(list_wallet_sessions)
+        .service(list_wallet_groups)
+        .service(list_groups)
+        .service(create_group)
+        .service(delete_group)
+        .service(list_group_wallets)
+        .service(add_group_wallet)
+        .service(remove_group_wallet)
+        .service(list_webhooks)
+        .service(create_webhook)
+        .service(delete_webhook)
+        .service(list_sessions)
+        .service(revoke_session)
+        .service(revoke_all_sessions)
+        .service(openapi_json);
+}
+
+/// HTTP date header value of midnight UTC of `date`, `None` if it is outside years 1970-9999
+/// which HTTP dates can represent.
+fn http_date(date: NaiveDate) -> Option<HeaderValue> {
+    if !(1970..10_000).contains(&date.year()) {
+        return None;
+    }
+    let seconds = u64::try_from(date.and_time(NaiveTime::MIN).timestamp()).ok()?;
+    let time = UNIX_EPOCH.checked_add(StdDuration::from_secs(seconds))?;
+    HttpDate::from(time).try_into_value().ok()
+}
+
+/// Replace error response of unversioned path with the one returned before versioning, see
+/// [`ApiError::legacy_error_response`].
+fn legacy_error<B: MessageBody + 'static>(response: ServiceResponse<B>) -> ServiceResponse {
+    let error = response.response().error().and_then(|err| {
+        err.as_error::<OAuthError>()
+            .map(|err| &err.0)
+            .or_else(|| err.as_error::<ApiError>())
+            .map(ApiError::legacy_error_response)
+    });
+    match error {
+        Some(error) => response.into_response(error),
+        None => response.map_into_boxed_body(),
+    }
+}
+
+/// Mark response of unversioned path as deprecated, pointing to its `/api/v1` successor.
+fn deprecate<B>(response: &mut ServiceResponse<B>) {
+    let request = response.request();
+    if request.match_pattern().is_none() {
+        return;
+    }
+    let path = request.path();
+    let successor = format!("{API_V1}{}", path.strip_prefix("/api").unwrap_or(path));
+    let sunset = request
+        .app_data::<web::Data<AppState>>()
+        .and_then(|app_state| http_date(app_state.config.legacy_api_sunset));
+    let headers = response.headers_mut();
+    headers.insert(
+        HeaderName::from_static("deprecation"),
+        HeaderValue::from_static(LEGACY_API_DEPRECATION),
+    );
+    if let Some(sunset) = sunset {
+        headers.insert(HeaderName::from_static("sunset"), sunset);
+    }
+    if let Ok(value) = HeaderValue::from_str(&format!("<{successor}>; rel=\"successor-version\"")) {
+        headers.insert(header::LINK, value);
+    }
+}
@@ -1,13 +1,31 @@
+pub mod audit;
+mod builder;
+pub use builder::{Avanguard, AvanguardBuilder};
+pub mod claims;
+pub mod cleanup;
+pub mod cli;
 mod config;
-pub use config::Config;
+pub use config::{
+    Config, ConfigError, LogFormat, Mode, RateLimitBackend, MIN_SECRET_LENGTH, RELOADABLE_FIELDS,
+};
+pub mod cors;
 pub mod crypto;
 pub mod db;
 mod error;
+pub mod events;
 mod http;
-pub use http::{config_service, Challenge, JwtToken, WalletAddress, WalletSignature};
+pub mod lockout;
+pub mod metrics;
+pub mod openapi;
+pub use http::{config_service, Challenge, JwtToken, WalletAddress, WalletSignature, API_V1};
 pub mod hex;
 mod random;
+pub mod ratelimit;
+pub mod secrets;
+pub mod server;
 pub mod state;
+pub mod telemetry;
+pub mod webhook;
 
 #[macro_use]
 extern crate serde;
@@ -0,0 +1,145 @@
+use std::net::IpAddr;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use sqlx::{query, query_scalar};
+
+use crate::{db::DbPool, Config};
+
+/// Temporary lockout applied after repeated failed login attempts.
+/// Lockout duration doubles with every failed attempt past the threshold.
+#[derive(Clone, Copy)]
+pub struct LockoutPolicy {
+    threshold: u32,
+    duration: u32,
+    max_duration: u32,
+}
+
+impl LockoutPolicy {
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        Self {
+            threshold: config.lockout_threshold,
+            duration: config.lockout_duration,
+            max_duration: config.lockout_max_duration,
+        }
+    }
+
+    /// Returns lockout duration in seconds after given number of failed attempts.
+    #[must_use]
+    pub fn lockout_seconds(&self, failed_attempts: i32) -> Option<u32> {
+        if self.threshold == 0 {
+            return None;
+        }
+        let excess = u32::try_from(failed_attempts)
+            .ok()?
+            .checked_sub(self.threshold)?;
+        let seconds = u64::from(self.duration) << excess.min(32);
+        Some(seconds.min(self.max_duration.into()) as u32)
+    }
+
+    /// Returns time until which login is locked after given number of failed attempts.
+    #[must_use]
+    pub fn locked_until(&self, failed_attempts: i32) -> Option<NaiveDateTime> {
+        self.lockout_seconds(failed_attempts)
+            .map(|seconds| (Utc::now() + Duration::seconds(seconds.into())).naive_utc())
+    }
+
+    /// Time after which failed attempts counter is reset.
+    #[must_use]
+    pub fn counter_cutoff(&self) -> NaiveDateTime {
+        (Utc::now() - Duration::seconds(self.max_duration.into())).naive_utc()
+    }
+
+    /// Returns time until which login from given IP is locked, if it is locked.
+    pub async fn ip_locked_until(
+        pool: &DbPool,
+        ip: IpAddr,
+    ) -> Result<Option<NaiveDateTime>, sqlx::Error> {
+        let locked_until = query_scalar!(
+            "SELECT locked_until FROM failed_login_ip WHERE ip = $1 AND locked_until > $2",
+            ip.to_string(),
+            Utc::now().naive_utc()
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(locked_until.flatten())
+    }
+
+    /// Increment failed attempts counter for IP, lock it if threshold has been reached.
+    /// Returns time until which IP is locked.
+    pub async fn register_ip_failure(
+        &self,
+        pool: &DbPool,
+        ip: IpAddr,
+    ) -> Result<Option<NaiveDateTime>, sqlx::Error> {
+        let ip = ip.to_string();
+        let failed_attempts = query_scalar!(
+            "INSERT INTO failed_login_ip (ip, failed_attempts, last_attempt_at) VALUES ($1, 1, $2) \
+            ON CONFLICT (ip) DO UPDATE SET \
+            failed_attempts = CASE WHEN failed_login_ip.last_attempt_at < $3 \
+                THEN 1 ELSE failed_login_ip.failed_attempts + 1 END, \
+            last_attempt_at = EXCLUDED.last_attempt_at \
+            RETURNING failed_attempts",
+            ip,
+            Utc::now().naive_utc(),
+            self.counter_cutoff(),
+        )
+        .fetch_one(pool)
+        .await?;
+        let locked_until = self.locked_until(failed_attempts);
+        if locked_until.is_some() {
+            log::warn!("Locking logins from IP {ip} after {failed_attempts} failed attempts");
+            query!(
+                "UPDATE failed_login_ip SET locked_until = $2 WHERE ip = $1",
+                ip,
+                locked_until
+            )
+            .execute(pool)
+            .await?;
+        }
+        Ok(locked_until)
+    }
+
+    /// Delete IP counters which were not updated since `cutoff`.
+    pub async fn delete_stale_ips(
+        pool: &DbPool,
+        cutoff: NaiveDateTime,
+    ) -> Result<u64, sqlx::Error> {
+        let result = query!(
+            "DELETE FROM failed_login_ip WHERE last_attempt_at < $1 \
+            AND (locked_until IS NULL OR locked_until < $1)",
+            cutoff
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn test_locked_until() {
+        let mut config = Config::parse_from(["avanguard"]);
+        config.lockout_threshold = 3;
+        config.lockout_duration = 60;
+        config.lockout_max_duration = 300;
+        let policy = LockoutPolicy::new(&config);
+
+        assert_eq!(policy.lockout_seconds(2), None);
+        assert_eq!(policy.lockout_seconds(3), Some(60));
+        assert_eq!(policy.lockout_seconds(4), Some(120));
+        assert_eq!(policy.lockout_seconds(5), Some(240));
+        assert_eq!(policy.lockout_seconds(6), Some(300));
+        assert_eq!(policy.lockout_seconds(100), Some(300));
+        assert!(policy.locked_until(2).is_none());
+        assert!(policy.locked_until(3).unwrap() > Utc::now().naive_utc());
+
+        config.lockout_threshold = 0;
+        assert_eq!(LockoutPolicy::new(&config).lockout_seconds(100), None);
+    }
+}
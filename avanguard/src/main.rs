@@ -1,47 +1,140 @@
-use std::net::{IpAddr, Ipv4Addr};
+use std::{fs, io, os::unix::fs::FileTypeExt};
 
-use actix_cors::Cors;
-use actix_web::{http::header, middleware, web, App, HttpServer};
-use anyhow::Result;
-use avanguard::{config_service, db::init_db, state::AppState, Config};
-use clap::Parser;
-use env_logger::Builder;
+use actix_web::{middleware, App, HttpServer};
+use anyhow::{bail, Context, Result};
+use avanguard::{
+    cleanup::run_cleanup_task,
+    cli::{run_command, Command},
+    cors::{cors, run_client_origins_task},
+    db::{connect_db, migrate, pending_migrations},
+    events::{connect_sink, run_publish_task},
+    metrics::RequestMetrics,
+    secrets::{load_secrets, run_secret_refresh_task, SecretProvider, VaultProvider},
+    server::{on_connect, run_cert_reload_task, tls_config, Listener},
+    state::spawn_reload_task,
+    telemetry::{init_tracing, shutdown_tracing, RequestTracing},
+    webhook::run_webhook_task,
+    Avanguard, Config,
+};
 
 #[macro_use]
 extern crate log;
 
+/// Default access log format extended with request id.
+const ACCESS_LOG_FORMAT: &str =
+    r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{x-request-id}o"#;
+
 #[actix_web::main]
 async fn main() -> Result<()> {
-    let config = Config::parse();
-    Builder::new().filter_level(config.log_level).init();
-    info!("AvanGuard HTTP server starting...");
+    let mut config = Config::load()?;
+    init_tracing(&config)?;
+
+    // Fetch secrets from secret manager
+    let secret_provider =
+        VaultProvider::new(&config)?.map(|provider| Box::new(provider) as Box<dyn SecretProvider>);
+    load_secrets(&mut config, secret_provider.as_deref()).await?;
 
     // Initialize DB connection
-    let pool = init_db(
+    let pool = connect_db(
         &config.db_host,
         config.db_port,
         &config.db_name,
         &config.db_user,
         &config.db_password,
+        config.db_connect_retries,
     )
-    .await;
-    let listen_port = config.listen_port;
-    HttpServer::new(move || {
-        let cors = Cors::default()
-            .allowed_origin(&config.client_origin_url)
-            .allowed_methods(vec!["GET", "POST"])
-            .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT])
-            .allowed_header(header::CONTENT_TYPE)
-            .max_age(3600);
+    .await
+    .with_context(|| {
+        format!(
+            "Cannot connect to database {}:{}/{}",
+            config.db_host, config.db_port, config.db_name
+        )
+    })?;
+
+    // Schema commands run against the database as it is
+    if let Some(command @ Command::Db(_)) = &config.command {
+        run_command(command, &config, &pool, &mut io::stdout()).await?;
+        shutdown_tracing();
+        return Ok(());
+    }
+
+    if config.no_auto_migrate {
+        let pending = pending_migrations(&pool)
+            .await
+            .context("Cannot check database schema")?;
+        if pending > 0 {
+            bail!("Database schema is behind by {pending} migrations, run `avanguard db migrate`");
+        }
+    } else {
+        migrate(&pool).await.context("Cannot migrate database")?;
+    }
+
+    if let Some(command) = &config.command {
+        run_command(command, &config, &pool, &mut io::stdout()).await?;
+        shutdown_tracing();
+        return Ok(());
+    }
+
+    info!("AvanGuard HTTP server starting...");
+    info!("Effective configuration:\n{}", config.report());
+
+    // Periodically purge stale records
+    actix_web::rt::spawn(run_cleanup_task(pool.clone(), config.clone()));
+    // Deliver queued webhook events
+    actix_web::rt::spawn(run_webhook_task(pool.clone(), config.clone()));
+    // Publish queued events to message bus
+    if let Some(url) = &config.event_sink {
+        let sink = connect_sink(url, &config.event_topic).await?;
+        info!("Publishing events to {url}");
+        actix_web::rt::spawn(run_publish_task(pool.clone(), config.clone(), sink));
+    }
+
+    let tls = tls_config(&config).context("Cannot load TLS certificate")?;
+    let avanguard = Avanguard::builder()
+        .config(config.clone())
+        .store(pool)
+        .legacy_api(true)
+        .metrics(true)
+        .health_probes(true)
+        .build()?;
+    // Apply configuration changes on SIGHUP
+    spawn_reload_task(avanguard.state())?;
+    // Pick up rotated secrets
+    actix_web::rt::spawn(run_secret_refresh_task(avanguard.state(), secret_provider));
+    // Allow origins of registered clients
+    actix_web::rt::spawn(run_client_origins_task(avanguard.state()));
+    let mut server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(AppState::new(config.clone(), pool.clone())))
-            .wrap(middleware::Logger::default())
-            .wrap(cors)
-            .configure(config_service)
+            // Read by middleware, which runs outside of Avanguard scope
+            .app_data(avanguard.state())
+            .wrap(RequestMetrics)
+            .wrap(RequestTracing)
+            .wrap(middleware::Logger::new(ACCESS_LOG_FORMAT))
+            .wrap(cors(avanguard.state()))
+            .service(avanguard.scope(""))
     })
-    .bind((IpAddr::V4(Ipv4Addr::UNSPECIFIED), listen_port))?
-    .run()
-    .await?;
+    .on_connect(on_connect);
+    for listener in config.listeners() {
+        info!("Listening on {listener}");
+        server = match (&listener, &tls) {
+            (Listener::Tcp(address), Some((tls_config, _))) => {
+                server.bind_rustls_021(address, tls_config.clone())?
+            }
+            (Listener::Tcp(address), None) => server.bind(address)?,
+            (Listener::Unix(path), _) => {
+                // Remove socket left by previous run
+                if fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                    fs::remove_file(path)?;
+                }
+                server.bind_uds(path)?
+            }
+        };
+    }
+    if let Some((_, resolver)) = tls {
+        actix_web::rt::spawn(run_cert_reload_task(resolver));
+    }
+    server.run().await?;
 
+    shutdown_tracing();
     Ok(())
 }
@@ -0,0 +1,197 @@
+use std::{
+    future::{ready, Ready},
+    time::Instant,
+};
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error,
+};
+use futures_util::future::LocalBoxFuture;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+
+use crate::{db::DbPool, error::ApiError, state::AppState};
+
+/// Outcome label of successfully handled requests, failures are labelled with `ApiError::code()`.
+pub(crate) const SUCCESS: &str = "Success";
+
+/// Prometheus metrics exported at `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: HistogramVec,
+    pub challenges: IntCounterVec,
+    pub logins: IntCounterVec,
+    pub refreshes: IntCounterVec,
+    pub revocations: IntCounter,
+    pub signature_verification: Histogram,
+    db_connections: IntGauge,
+    db_idle_connections: IntGauge,
+}
+
+impl Metrics {
+    #[must_use]
+    pub fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("avanguard".into()), None).expect("Invalid metrics prefix");
+        let http_requests = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request duration in seconds",
+            ),
+            &["method", "route", "status"],
+        )
+        .expect("Invalid metric");
+        let challenges = IntCounterVec::new(
+            Opts::new(
+                "challenges_total",
+                "Number of challenge requests by outcome",
+            ),
+            &["outcome"],
+        )
+        .expect("Invalid metric");
+        let logins = IntCounterVec::new(
+            Opts::new("logins_total", "Number of login attempts by outcome"),
+            &["outcome"],
+        )
+        .expect("Invalid metric");
+        let refreshes = IntCounterVec::new(
+            Opts::new(
+                "refreshes_total",
+                "Number of token refresh attempts by outcome",
+            ),
+            &["outcome"],
+        )
+        .expect("Invalid metric");
+        let revocations = IntCounter::new("revocations_total", "Number of revoked refresh tokens")
+            .expect("Invalid metric");
+        let signature_verification = Histogram::with_opts(
+            HistogramOpts::new(
+                "signature_verification_duration_seconds",
+                "Wallet signature verification duration in seconds",
+            )
+            .buckets(vec![0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01]),
+        )
+        .expect("Invalid metric");
+        let db_connections =
+            IntGauge::new("db_pool_connections", "Number of open database connections")
+                .expect("Invalid metric");
+        let db_idle_connections = IntGauge::new(
+            "db_pool_idle_connections",
+            "Number of idle database connections",
+        )
+        .expect("Invalid metric");
+
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(challenges.clone()),
+            Box::new(logins.clone()),
+            Box::new(refreshes.clone()),
+            Box::new(revocations.clone()),
+            Box::new(signature_verification.clone()),
+            Box::new(db_connections.clone()),
+            Box::new(db_idle_connections.clone()),
+        ] {
+            registry.register(collector).expect("Duplicate metric");
+        }
+
+        Self {
+            registry,
+            http_requests,
+            challenges,
+            logins,
+            refreshes,
+            revocations,
+            signature_verification,
+            db_connections,
+            db_idle_connections,
+        }
+    }
+
+    /// Increment counter with label matching result: success or error code.
+    pub fn count<T>(counter: &IntCounterVec, result: &Result<T, ApiError>) {
+        let outcome = match result {
+            Ok(_) => SUCCESS,
+            Err(err) => err.code(),
+        };
+        counter.with_label_values(&[outcome]).inc();
+    }
+
+    /// Render metrics in Prometheus text format.
+    #[must_use]
+    pub fn render(&self, pool: &DbPool) -> String {
+        self.db_connections.set(pool.size().into());
+        self.db_idle_connections.set(pool.num_idle() as i64);
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Failed to encode metrics");
+        String::from_utf8(buffer).expect("Metrics are not valid UTF-8")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Middleware recording duration of HTTP requests per route.
+pub struct RequestMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware { service }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let app_state = req.app_data::<web::Data<AppState>>().cloned();
+        let method = req.method().to_string();
+        let future = self.service.call(req);
+        Box::pin(async move {
+            let response = future.await?;
+            if let Some(app_state) = app_state {
+                // Use route pattern to keep label cardinality bounded
+                let route = response
+                    .request()
+                    .match_pattern()
+                    .unwrap_or_else(|| String::from("unmatched"));
+                app_state
+                    .metrics
+                    .http_requests
+                    .with_label_values(&[&method, &route, response.status().as_str()])
+                    .observe(start.elapsed().as_secs_f64());
+            }
+            Ok(response)
+        })
+    }
+}
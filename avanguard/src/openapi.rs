@@ -0,0 +1,105 @@
+use actix_web::{get, web::Json};
+use utoipa::{
+    openapi::{
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+        OpenApi as OpenApiDoc,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{
+    db::Session,
+    error::{ErrorInfo, OAuthErrorInfo},
+    http::{
+        __path_list_sessions, __path_refresh, __path_revoke_all_sessions, __path_revoke_session,
+        __path_web3auth_end, __path_web3auth_start, Challenge, JwtToken, RefreshTokenRequest,
+        WalletAddress, WalletSignature, API_V1,
+    },
+};
+
+/// OpenAPI description of the endpoints used by frontends, generated from handler types.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "AvanGuard",
+        description = "OIDC identity provider authenticating Ethereum wallet owners"
+    ),
+    paths(
+        web3auth_start,
+        web3auth_end,
+        refresh,
+        list_sessions,
+        revoke_session,
+        revoke_all_sessions
+    ),
+    components(schemas(
+        Challenge,
+        WalletAddress,
+        WalletSignature,
+        JwtToken,
+        RefreshTokenRequest,
+        Session,
+        ErrorInfo,
+        OAuthErrorInfo
+    )),
+    modifiers(&IdTokenAuth, &NoLicense, &VersionPrefix),
+    tags(
+        (name = "auth", description = "Wallet authentication"),
+        (name = "session", description = "Sessions of the authenticated wallet owner")
+    )
+)]
+pub struct ApiDoc;
+
+/// Adds `Authorization: Bearer <id token>` scheme required by session endpoints.
+struct IdTokenAuth;
+
+impl Modify for IdTokenAuth {
+    fn modify(&self, openapi: &mut OpenApiDoc) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "id_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Removes license taken from the package manifest, which doesn't declare one.
+struct NoLicense;
+
+impl Modify for NoLicense {
+    fn modify(&self, openapi: &mut OpenApiDoc) {
+        openapi.info.license = None;
+    }
+}
+
+/// Prefixes paths, which are relative to the version scope in handlers, with [`API_V1`].
+struct VersionPrefix;
+
+impl Modify for VersionPrefix {
+    fn modify(&self, openapi: &mut OpenApiDoc) {
+        let paths = std::mem::take(&mut openapi.paths.paths);
+        openapi.paths.paths = paths
+            .into_iter()
+            .map(|(path, item)| (format!("{API_V1}{path}"), item))
+            .collect();
+    }
+}
+
+/// OpenAPI 3 specification of the API.
+#[get("/openapi.json")]
+pub(crate) async fn openapi_json() -> Json<OpenApiDoc> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI served at `/api/docs/`, rendering the specification from `/api/v1/openapi.json`.
+#[cfg(feature = "swagger-ui")]
+pub(crate) fn swagger_ui() -> utoipa_swagger_ui::SwaggerUi {
+    utoipa_swagger_ui::SwaggerUi::new("/api/docs/{_:.*}").config(utoipa_swagger_ui::Config::from(
+        format!("{API_V1}/openapi.json"),
+    ))
+}
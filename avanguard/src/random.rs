@@ -1,10 +1,11 @@
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use rand::{rngs::OsRng, RngCore};
 
+use crate::hex::to_lower_hex;
+
+/// Generate `n` random bytes from OS random source, encoded as lowercase hex.
 #[must_use]
-pub(crate) fn gen_alphanumeric(n: usize) -> String {
-    thread_rng()
-        .sample_iter(Alphanumeric)
-        .take(n)
-        .map(char::from)
-        .collect()
+pub(crate) fn gen_hex(n: usize) -> String {
+    let mut bytes = vec![0u8; n];
+    OsRng.fill_bytes(&mut bytes);
+    to_lower_hex(&bytes)
 }
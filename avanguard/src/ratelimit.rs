@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration as StdDuration, Instant},
+};
+
+use actix_web::HttpRequest;
+use chrono::{Duration, NaiveDateTime, Utc};
+use sqlx::query;
+
+use crate::{db::DbPool, error::ApiError, Config, RateLimitBackend};
+
+/// Header set by reverse proxies with the chain of client addresses.
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// Number of in-memory counters after which expired ones are purged.
+const MEMORY_PURGE_THRESHOLD: usize = 10_000;
+
+enum Store {
+    Memory(Mutex<HashMap<String, (Instant, u32)>>),
+    Postgres(DbPool),
+}
+
+/// Fixed window rate limiter for auth endpoints, keyed by client IP and wallet address.
+/// Window and limits can be changed at runtime with [`RateLimiter::set_limits`].
+pub struct RateLimiter {
+    store: Store,
+    window: AtomicU32,
+    ip_limit: AtomicU32,
+    address_limit: AtomicU32,
+    trusted_proxies: Vec<IpAddr>,
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn new(config: &Config, pool: DbPool) -> Self {
+        let store = match config.rate_limit_backend {
+            RateLimitBackend::Memory => Store::Memory(Mutex::default()),
+            RateLimitBackend::Postgres => Store::Postgres(pool),
+        };
+        Self {
+            store,
+            window: AtomicU32::new(config.rate_limit_window.max(1)),
+            ip_limit: AtomicU32::new(config.rate_limit_ip),
+            address_limit: AtomicU32::new(config.rate_limit_address),
+            trusted_proxies: config.trusted_proxies.clone(),
+        }
+    }
+
+    /// Apply rate limit window and limits from `config`.
+    pub fn set_limits(&self, config: &Config) {
+        self.window
+            .store(config.rate_limit_window.max(1), Ordering::Relaxed);
+        self.ip_limit.store(config.rate_limit_ip, Ordering::Relaxed);
+        self.address_limit
+            .store(config.rate_limit_address, Ordering::Relaxed);
+    }
+
+    fn window(&self) -> u32 {
+        self.window.load(Ordering::Relaxed)
+    }
+
+    /// Determine client IP. `X-Forwarded-For` header is only honoured when the request comes
+    /// from a trusted proxy, in which case the rightmost untrusted address is used.
+    #[must_use]
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer_ip = req.peer_addr()?.ip();
+        if !self.trusted_proxies.contains(&peer_ip) {
+            return Some(peer_ip);
+        }
+        let forwarded_ip = req
+            .headers()
+            .get_all(FORWARDED_FOR_HEADER)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+            .rev()
+            .find(|ip| !self.trusted_proxies.contains(ip));
+        Some(forwarded_ip.unwrap_or(peer_ip))
+    }
+
+    /// Count request to `route` from client IP.
+    pub async fn check_ip(&self, route: &str, req: &HttpRequest) -> Result<(), ApiError> {
+        match self.client_ip(req) {
+            Some(ip) => {
+                let limit = self.ip_limit.load(Ordering::Relaxed);
+                self.check(&format!("{route}:ip:{ip}"), limit).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Count request to `route` for wallet address.
+    pub async fn check_address(&self, route: &str, address: &str) -> Result<(), ApiError> {
+        let limit = self.address_limit.load(Ordering::Relaxed);
+        self.check(&format!("{route}:address:{address}"), limit)
+            .await
+    }
+
+    /// Register a hit for `key`. Returns `ApiError::RateLimited` if `limit` has been exceeded
+    /// in current window.
+    async fn check(&self, key: &str, limit: u32) -> Result<(), ApiError> {
+        if limit == 0 {
+            return Ok(());
+        }
+        let (hits, retry_after) = match &self.store {
+            Store::Memory(counters) => self.hit_memory(counters, key),
+            Store::Postgres(pool) => self.hit_postgres(pool, key).await?,
+        };
+        if hits > limit {
+            log::debug!("Rate limit exceeded for {key}");
+            Err(ApiError::RateLimited(retry_after.max(1)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns number of hits in current window and seconds left until the window ends.
+    fn hit_memory(
+        &self,
+        counters: &Mutex<HashMap<String, (Instant, u32)>>,
+        key: &str,
+    ) -> (u32, u64) {
+        let window = StdDuration::from_secs(self.window().into());
+        let now = Instant::now();
+        let mut counters = counters.lock().expect("rate limit counters lock poisoned");
+        if counters.len() > MEMORY_PURGE_THRESHOLD {
+            counters.retain(|_, (start, _)| now.duration_since(*start) < window);
+        }
+        let (start, hits) = counters.entry(key.to_string()).or_insert((now, 0));
+        if now.duration_since(*start) >= window {
+            *start = now;
+            *hits = 0;
+        }
+        *hits += 1;
+        let retry_after = (window - now.duration_since(*start)).as_secs();
+        (*hits, retry_after)
+    }
+
+    /// Returns number of hits in current window and seconds left until the window ends.
+    async fn hit_postgres(&self, pool: &DbPool, key: &str) -> Result<(u32, u64), sqlx::Error> {
+        let now = Utc::now().naive_utc();
+        let window = Duration::seconds(self.window().into());
+        let record = query!(
+            "INSERT INTO rate_limit (key, window_start, hits) VALUES ($1, $2, 1) \
+            ON CONFLICT (key) DO UPDATE SET \
+            window_start = CASE WHEN rate_limit.window_start <= $3 \
+                THEN EXCLUDED.window_start ELSE rate_limit.window_start END, \
+            hits = CASE WHEN rate_limit.window_start <= $3 \
+                THEN 1 ELSE rate_limit.hits + 1 END \
+            RETURNING window_start, hits",
+            key,
+            now,
+            now - window,
+        )
+        .fetch_one(pool)
+        .await?;
+        let retry_after = (record.window_start + window - now).num_seconds().max(0);
+        Ok((record.hits as u32, retry_after as u64))
+    }
+
+    /// Delete Postgres counters of windows which started before `cutoff`.
+    pub async fn delete_expired(pool: &DbPool, cutoff: NaiveDateTime) -> Result<u64, sqlx::Error> {
+        let result = query!("DELETE FROM rate_limit WHERE window_start < $1", cutoff)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::test::TestRequest;
+    use clap::Parser;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_client_ip() {
+        let mut config = Config::parse_from(["avanguard"]);
+        config.trusted_proxies = vec!["10.0.0.1".parse().unwrap()];
+        let pool = DbPool::connect_lazy("postgres://localhost").unwrap();
+        let limiter = RateLimiter::new(&config, pool);
+
+        // Direct connection, header is ignored
+        let req = TestRequest::default()
+            .peer_addr("192.168.1.1:1234".parse().unwrap())
+            .insert_header((FORWARDED_FOR_HEADER, "1.1.1.1"))
+            .to_http_request();
+        assert_eq!(
+            limiter.client_ip(&req),
+            Some("192.168.1.1".parse().unwrap())
+        );
+
+        // Connection from trusted proxy
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .insert_header((FORWARDED_FOR_HEADER, "1.1.1.1, 2.2.2.2, 10.0.0.1"))
+            .to_http_request();
+        assert_eq!(limiter.client_ip(&req), Some("2.2.2.2".parse().unwrap()));
+
+        // Trusted proxy without header
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .to_http_request();
+        assert_eq!(limiter.client_ip(&req), Some("10.0.0.1".parse().unwrap()));
+    }
+
+    #[actix_web::test]
+    async fn test_memory_limit() {
+        let mut config = Config::parse_from(["avanguard"]);
+        config.rate_limit_address = 2;
+        let pool = DbPool::connect_lazy("postgres://localhost").unwrap();
+        let limiter = RateLimiter::new(&config, pool);
+
+        assert!(limiter.check_address("auth", "0x01").await.is_ok());
+        assert!(limiter.check_address("auth", "0x01").await.is_ok());
+        assert!(matches!(
+            limiter.check_address("auth", "0x01").await,
+            Err(ApiError::RateLimited(_))
+        ));
+        // Other addresses and routes are counted separately
+        assert!(limiter.check_address("auth", "0x02").await.is_ok());
+        assert!(limiter.check_address("refresh", "0x01").await.is_ok());
+
+        // Limits can be changed at runtime
+        config.rate_limit_address = 0;
+        limiter.set_limits(&config);
+        assert!(limiter.check_address("auth", "0x01").await.is_ok());
+    }
+}
@@ -0,0 +1,187 @@
+use std::{collections::HashMap, fs, path::PathBuf, time::Duration as StdDuration};
+
+use actix_web::web;
+use async_trait::async_trait;
+use openidconnect::url::Url;
+use thiserror::Error;
+
+use crate::{state::AppState, Config, ConfigError};
+
+/// Header with Vault token.
+const VAULT_TOKEN_HEADER: &str = "x-vault-token";
+
+/// Timeout of requests to secret managers.
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("failed to fetch secrets: {0}")]
+    Fetch(String),
+}
+
+/// Secrets used at runtime, which can be rotated without restart.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secrets {
+    pub client_secret: String,
+    pub refresh_token_secret: String,
+    pub admin_token: Option<String>,
+    pub db_password: String,
+}
+
+impl From<&Config> for Secrets {
+    fn from(config: &Config) -> Self {
+        Self {
+            client_secret: config.client_secret.clone(),
+            refresh_token_secret: config.refresh_token_secret.clone(),
+            admin_token: config.admin_token.clone(),
+            db_password: config.db_password.clone(),
+        }
+    }
+}
+
+/// Secret manager secrets are fetched from.
+#[async_trait]
+pub trait SecretProvider: Send + Sync {
+    /// Fetch secrets by name: `client_secret`, `db_password`, `refresh_token_secret`
+    /// or `admin_token`. Secrets missing from the result keep their configured values.
+    async fn fetch(&self) -> Result<HashMap<String, String>, SecretError>;
+}
+
+/// Reads secrets from HashiCorp Vault KV version 2 secrets engine.
+pub struct VaultProvider {
+    client: reqwest::Client,
+    url: Url,
+    token: String,
+    /// Token is read again before every request, as token files are rotated by Vault agents.
+    token_file: Option<PathBuf>,
+}
+
+impl VaultProvider {
+    /// Create provider if Vault address is configured.
+    pub fn new(config: &Config) -> Result<Option<Self>, SecretError> {
+        let Some(addr) = &config.vault_addr else {
+            return Ok(None);
+        };
+        let token = config
+            .vault_token
+            .clone()
+            .ok_or_else(|| SecretError::Fetch("Vault token is not set".into()))?;
+        let url = addr
+            .join(&format!(
+                "v1/{}/data/{}",
+                config.vault_mount.trim_matches('/'),
+                config.vault_path.trim_matches('/')
+            ))
+            .map_err(|err| SecretError::Fetch(err.to_string()))?;
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|err| SecretError::Fetch(err.to_string()))?;
+        Ok(Some(Self {
+            client,
+            url,
+            token,
+            token_file: config.vault_token_file.clone(),
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+struct VaultResponse {
+    data: VaultData,
+}
+
+#[derive(Deserialize)]
+struct VaultData {
+    data: HashMap<String, String>,
+}
+
+#[async_trait]
+impl SecretProvider for VaultProvider {
+    async fn fetch(&self) -> Result<HashMap<String, String>, SecretError> {
+        let token = match &self.token_file {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|err| SecretError::Fetch(format!("{}: {err}", path.display())))?
+                .trim_end()
+                .to_string(),
+            None => self.token.clone(),
+        };
+        let response = self
+            .client
+            .get(self.url.clone())
+            .header(VAULT_TOKEN_HEADER, token)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|err| SecretError::Fetch(err.to_string()))?;
+        let body = response
+            .text()
+            .await
+            .map_err(|err| SecretError::Fetch(err.to_string()))?;
+        let response: VaultResponse =
+            serde_json::from_str(&body).map_err(|err| SecretError::Fetch(err.to_string()))?;
+        Ok(response.data.data)
+    }
+}
+
+/// Apply secrets fetched from `provider` to `config` and check they are safe to use.
+pub async fn load_secrets(
+    config: &mut Config,
+    provider: Option<&dyn SecretProvider>,
+) -> Result<(), SecretError> {
+    if let Some(provider) = provider {
+        for (name, secret) in provider.fetch().await? {
+            match name.as_str() {
+                "client_secret" => config.client_secret = secret,
+                "db_password" => config.db_password = secret,
+                "refresh_token_secret" => config.refresh_token_secret = secret,
+                "admin_token" => config.admin_token = Some(secret),
+                _ => log::debug!("Ignoring unknown secret {name}"),
+            }
+        }
+    }
+    config.validate_secrets()?;
+    Ok(())
+}
+
+/// Read secrets from files and `provider` again. Returns whether any secret changed.
+pub async fn refresh_secrets(
+    app_state: &AppState,
+    provider: Option<&dyn SecretProvider>,
+) -> Result<bool, SecretError> {
+    let mut config = app_state.config.clone();
+    config.read_secret_files()?;
+    load_secrets(&mut config, provider).await?;
+    Ok(app_state.set_secrets(Secrets::from(&config)))
+}
+
+/// Refresh secrets periodically, every `secret_refresh_interval` seconds, so rotations
+/// take effect. Failed refresh keeps current secrets.
+pub async fn run_secret_refresh_task(
+    app_state: web::Data<AppState>,
+    provider: Option<Box<dyn SecretProvider>>,
+) {
+    let config = &app_state.config;
+    let uses_files = config.client_secret_file.is_some()
+        || config.db_password_file.is_some()
+        || config.refresh_token_secret_file.is_some()
+        || config.admin_token_file.is_some();
+    if config.secret_refresh_interval == 0 || !(uses_files || provider.is_some()) {
+        return;
+    }
+    let mut interval = actix_web::rt::time::interval(StdDuration::from_secs(
+        config.secret_refresh_interval.into(),
+    ));
+    // First tick completes immediately, secrets have just been loaded
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match refresh_secrets(&app_state, provider.as_deref()).await {
+            Ok(true) => log::info!("Secrets rotated"),
+            Ok(false) => log::debug!("Secrets unchanged"),
+            Err(err) => log::error!("Secret refresh failed: {err}"),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    any::Any,
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration as StdDuration, SystemTime},
+};
+
+use actix_tls::accept::rustls_0_21::TlsStream;
+use actix_web::{dev::Extensions, rt::net::TcpStream};
+use rustls::{
+    server::{AllowAnyAnonymousOrAuthenticatedClient, ClientHello, ResolvesServerCert},
+    sign::{any_supported_type, CertifiedKey},
+    Certificate, PrivateKey, RootCertStore, ServerConfig,
+};
+use rustls_pemfile::Item;
+use serde::{Serialize, Serializer};
+
+use crate::Config;
+
+/// Time between checks whether certificate or key files changed.
+const CERT_CHECK_INTERVAL: StdDuration = StdDuration::from_secs(10);
+
+/// Address the HTTP server listens on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Listener {
+    /// TCP socket, TLS is used if configured.
+    Tcp(SocketAddr),
+    /// Unix domain socket, always without TLS.
+    Unix(PathBuf),
+}
+
+impl FromStr for Listener {
+    type Err = String;
+
+    fn from_str(listener: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = listener.strip_prefix("unix:") {
+            Ok(Self::Unix(path.into()))
+        } else {
+            listener.parse().map(Self::Tcp).map_err(|_| {
+                format!(
+                    "invalid listener {listener}, expected <ip>:<port>, [<ipv6>]:<port> or unix:<path>"
+                )
+            })
+        }
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(address) => write!(f, "{address}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl Serialize for Listener {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Verified TLS client certificate of the connection, in DER format.
+#[derive(Clone)]
+pub struct ClientCertificate(pub Vec<u8>);
+
+/// Store verified TLS client certificate in connection data, see [`ClientCertificate`].
+pub fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+    if let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() {
+        let (_, session) = stream.get_ref();
+        if let Some(certificate) = session
+            .peer_certificates()
+            .and_then(|certificates| certificates.first())
+        {
+            data.insert(ClientCertificate(certificate.0.clone()));
+        }
+    }
+}
+
+fn read_pem(path: &Path) -> io::Result<Vec<Item>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::read_all(&mut reader)
+}
+
+fn invalid_data(path: &Path, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {message}", path.display()),
+    )
+}
+
+/// Load PEM certificate chain and private key.
+pub fn load_certified_key(cert: &Path, key: &Path) -> io::Result<CertifiedKey> {
+    let chain: Vec<Certificate> = read_pem(cert)?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(der) => Some(Certificate(der)),
+            _ => None,
+        })
+        .collect();
+    if chain.is_empty() {
+        return Err(invalid_data(cert, "no certificates found"));
+    }
+    let private_key = read_pem(key)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => Some(PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| invalid_data(key, "no private key found"))?;
+    let signing_key =
+        any_supported_type(&private_key).map_err(|err| invalid_data(key, &err.to_string()))?;
+    Ok(CertifiedKey::new(chain, signing_key))
+}
+
+/// Serves certificate which can be replaced while the server is running.
+pub struct CertResolver {
+    cert: PathBuf,
+    key: PathBuf,
+    certified_key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+    pub fn new(cert: PathBuf, key: PathBuf) -> io::Result<Self> {
+        let certified_key = load_certified_key(&cert, &key)?;
+        Ok(Self {
+            cert,
+            key,
+            certified_key: RwLock::new(Arc::new(certified_key)),
+        })
+    }
+
+    /// Load certificate and key files again. Current certificate is kept if loading fails.
+    pub fn reload(&self) -> io::Result<()> {
+        let certified_key = load_certified_key(&self.cert, &self.key)?;
+        *self
+            .certified_key
+            .write()
+            .expect("certificate lock poisoned") = Arc::new(certified_key);
+        Ok(())
+    }
+
+    /// Latest modification time of certificate and key files.
+    fn modified(&self) -> io::Result<SystemTime> {
+        let cert = fs::metadata(&self.cert)?.modified()?;
+        let key = fs::metadata(&self.key)?.modified()?;
+        Ok(cert.max(key))
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.certified_key
+                .read()
+                .expect("certificate lock poisoned")
+                .clone(),
+        )
+    }
+}
+
+/// Build TLS configuration if certificate is configured. Client certificates signed by
+/// `admin_client_ca` are verified if it is set, but not required to connect.
+pub fn tls_config(config: &Config) -> io::Result<Option<(ServerConfig, Arc<CertResolver>)>> {
+    let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) else {
+        return Ok(None);
+    };
+    let resolver = Arc::new(CertResolver::new(cert.clone(), key.clone())?);
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &config.admin_client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for item in read_pem(ca)? {
+                if let Item::X509Certificate(der) = item {
+                    roots
+                        .add(&Certificate(der))
+                        .map_err(|err| invalid_data(ca, &err.to_string()))?;
+                }
+            }
+            if roots.is_empty() {
+                return Err(invalid_data(ca, "no certificates found"));
+            }
+            builder.with_client_cert_verifier(
+                AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed(),
+            )
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let tls_config = builder.with_cert_resolver(resolver.clone());
+    Ok(Some((tls_config, resolver)))
+}
+
+/// Reload certificate when its files change.
+pub async fn run_cert_reload_task(resolver: Arc<CertResolver>) {
+    let mut interval = actix_web::rt::time::interval(CERT_CHECK_INTERVAL);
+    let mut last_modified = resolver.modified().ok();
+    loop {
+        interval.tick().await;
+        let modified = resolver.modified().ok();
+        if modified == last_modified {
+            continue;
+        }
+        match resolver.reload() {
+            Ok(()) => {
+                log::info!("TLS certificate reloaded");
+                last_modified = modified;
+            }
+            // Files may be in the middle of being replaced, retry on next check
+            Err(err) => log::error!("Failed to reload TLS certificate: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_listener() {
+        assert_eq!(
+            "0.0.0.0:8080".parse(),
+            Ok(Listener::Tcp("0.0.0.0:8080".parse().unwrap()))
+        );
+        assert_eq!(
+            "[::1]:8080".parse(),
+            Ok(Listener::Tcp("[::1]:8080".parse().unwrap()))
+        );
+        assert_eq!(
+            "unix:/run/avanguard.sock".parse(),
+            Ok(Listener::Unix("/run/avanguard.sock".into()))
+        );
+        assert!("localhost".parse::<Listener>().is_err());
+        assert_eq!(
+            Listener::Unix("/run/avanguard.sock".into()).to_string(),
+            "unix:/run/avanguard.sock"
+        );
+    }
+}
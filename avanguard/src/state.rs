@@ -1,13 +1,246 @@
-use crate::{db::DbPool, Config};
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    audit::AuditLog,
+    claims::{ClaimsProvider, GroupsClaims, StaticClaims},
+    cors::OriginPattern,
+    db::{Client, DbPool},
+    events::{AuthEvent, EventHook},
+    lockout::LockoutPolicy,
+    metrics::Metrics,
+    ratelimit::RateLimiter,
+    secrets::Secrets,
+    telemetry::set_log_level,
+    Config, ConfigError,
+};
+
+/// Current values of reloadable fields, other than log level and rate limits.
+struct Reloadable {
+    client_origin_url: Vec<OriginPattern>,
+    challenge_template: String,
+}
+
+impl From<&Config> for Reloadable {
+    fn from(config: &Config) -> Self {
+        Self {
+            client_origin_url: config.client_origin_url.clone(),
+            challenge_template: config.challenge_template().into(),
+        }
+    }
+}
+
+/// Refresh token secret replaced by rotation.
+struct RetiredSecret {
+    secret: String,
+    /// When the last refresh token hashed with the secret expires.
+    until: Instant,
+}
+
+/// Client ID tokens are issued to, with the secret they are signed with.
+#[derive(Clone)]
+pub struct ClientCredentials {
+    pub client_id: String,
+    pub secret: String,
+}
 
 pub struct AppState {
+    /// Configuration loaded at startup, see [`AppState::reload`] for fields which can change.
     pub config: Config,
     pub pool: DbPool,
+    pub rate_limiter: RateLimiter,
+    pub lockout_policy: LockoutPolicy,
+    pub metrics: Metrics,
+    pub audit: AuditLog,
+    reloadable: RwLock<Reloadable>,
+    /// Allowed origins of registered clients, see [`AppState::load_client_origins`].
+    client_origins: RwLock<Vec<OriginPattern>>,
+    secrets: RwLock<Secrets>,
+    retired_refresh_token_secret: RwLock<Option<RetiredSecret>>,
+    /// Clients given to [`AvanguardBuilder`](crate::AvanguardBuilder) besides the configured one.
+    pub(crate) clients: Vec<ClientCredentials>,
+    pub(crate) hooks: Vec<Arc<dyn EventHook>>,
+    /// Sources of custom claims, merged in order.
+    pub(crate) claims_providers: Vec<Arc<dyn ClaimsProvider>>,
 }
 
 impl AppState {
+    /// Fails if the audit log can't be opened.
+    pub fn new(config: Config, pool: DbPool) -> Result<Self, ConfigError> {
+        let rate_limiter = RateLimiter::new(&config, pool.clone());
+        let lockout_policy = LockoutPolicy::new(&config);
+        let audit = AuditLog::open(config.audit_log.as_ref()).map_err(ConfigError::AuditLog)?;
+        let reloadable = RwLock::new(Reloadable::from(&config));
+        let secrets = RwLock::new(Secrets::from(&config));
+        let mut claims_providers: Vec<Arc<dyn ClaimsProvider>> = vec![Arc::new(GroupsClaims::new(
+            pool.clone(),
+            config.groups_claim.clone(),
+        ))];
+        if config.static_claims {
+            claims_providers.push(Arc::new(StaticClaims::new(pool.clone())));
+        }
+        Ok(Self {
+            config,
+            pool,
+            rate_limiter,
+            lockout_policy,
+            metrics: Metrics::new(),
+            audit,
+            reloadable,
+            client_origins: RwLock::default(),
+            secrets,
+            retired_refresh_token_secret: RwLock::default(),
+            clients: Vec::new(),
+            hooks: Vec::new(),
+            claims_providers,
+        })
+    }
+
+    /// Current secrets, which may differ from `config` values after rotation.
+    #[must_use]
+    pub fn secrets(&self) -> Secrets {
+        self.secrets.read().expect("secrets lock poisoned").clone()
+    }
+
+    /// Secrets refresh tokens are looked up with: the current one and, until tokens issued
+    /// before the last rotation expire, the previous one.
+    #[must_use]
+    pub fn refresh_token_secrets(&self) -> Vec<String> {
+        let mut secrets = vec![self.secrets().refresh_token_secret];
+        let retired = self
+            .retired_refresh_token_secret
+            .read()
+            .expect("secrets lock poisoned");
+        if let Some(retired) = retired.as_ref() {
+            if retired.until > Instant::now() {
+                secrets.push(retired.secret.clone());
+            }
+        }
+        secrets
+    }
+
+    /// Credentials of client `client_id`, or of the configured client if `None`.
+    /// Returns `None` if no such client is registered.
+    pub async fn client_credentials(
+        &self,
+        client_id: Option<&str>,
+    ) -> Result<Option<ClientCredentials>, sqlx::Error> {
+        match client_id {
+            Some(client_id) if client_id != self.config.client_id => {
+                if let Some(client) = self.clients.iter().find(|c| c.client_id == client_id) {
+                    return Ok(Some(client.clone()));
+                }
+                let client = Client::find_by_client_id(&self.pool, client_id).await?;
+                Ok(client.map(|client| ClientCredentials {
+                    client_id: client.client_id,
+                    secret: client.secret,
+                }))
+            }
+            _ => Ok(Some(ClientCredentials {
+                client_id: self.config.client_id.clone(),
+                secret: self.secrets().client_secret,
+            })),
+        }
+    }
+
+    /// Notify hooks about committed event.
+    pub fn notify(&self, event: &AuthEvent) {
+        for hook in &self.hooks {
+            hook.on_event(event);
+        }
+    }
+
+    /// Replace secrets. New database password is used for new connections. Refresh tokens
+    /// hashed with the replaced refresh token secret stay valid until they expire.
+    /// Returns whether any secret changed.
+    pub fn set_secrets(&self, secrets: Secrets) -> bool {
+        let mut current = self.secrets.write().expect("secrets lock poisoned");
+        if *current == secrets {
+            return false;
+        }
+        if current.refresh_token_secret != secrets.refresh_token_secret {
+            let until =
+                Instant::now() + Duration::from_secs(self.config.refresh_token_timeout.into());
+            *self
+                .retired_refresh_token_secret
+                .write()
+                .expect("secrets lock poisoned") = Some(RetiredSecret {
+                secret: current.refresh_token_secret.clone(),
+                until,
+            });
+        }
+        if current.db_password != secrets.db_password {
+            let options = self.pool.connect_options().as_ref().clone();
+            self.pool
+                .set_connect_options(options.password(&secrets.db_password));
+        }
+        *current = secrets;
+        true
+    }
+
+    /// Apply values of [`RELOADABLE_FIELDS`](crate::RELOADABLE_FIELDS) from `config`.
+    pub fn reload(&self, config: &Config) {
+        set_log_level(config.log_level);
+        self.rate_limiter.set_limits(config);
+        *self.reloadable.write().expect("config lock poisoned") = Reloadable::from(config);
+    }
+
+    /// Whether CORS requests from `origin` are allowed, by configuration or by registered clients.
     #[must_use]
-    pub fn new(config: Config, pool: DbPool) -> Self {
-        Self { config, pool }
+    pub fn origin_allowed(&self, origin: &str) -> bool {
+        let reloadable = self.reloadable.read().expect("config lock poisoned");
+        let client_origins = self.client_origins.read().expect("config lock poisoned");
+        reloadable
+            .client_origin_url
+            .iter()
+            .chain(client_origins.iter())
+            .any(|pattern| pattern.matches(origin))
     }
+
+    /// Load allowed origins of registered clients. Invalid origins are logged and skipped.
+    pub async fn load_client_origins(&self) -> Result<(), sqlx::Error> {
+        let origins = Client::all_origins(&self.pool)
+            .await?
+            .into_iter()
+            .filter_map(|origin| match origin.parse() {
+                Ok(pattern) => Some(pattern),
+                Err(err) => {
+                    log::warn!("Ignoring allowed origin of registered client: {err}");
+                    None
+                }
+            })
+            .collect();
+        *self.client_origins.write().expect("config lock poisoned") = origins;
+        Ok(())
+    }
+
+    /// Message included in challenges of new wallets.
+    #[must_use]
+    pub fn challenge_template(&self) -> String {
+        let reloadable = self.reloadable.read().expect("config lock poisoned");
+        reloadable.challenge_template.clone()
+    }
+}
+
+/// Reload configuration on SIGHUP. Invalid configuration is logged and ignored.
+#[cfg(unix)]
+pub fn spawn_reload_task(app_state: actix_web::web::Data<AppState>) -> std::io::Result<()> {
+    use actix_web::rt::signal::unix::{signal, SignalKind};
+
+    // Register handler before returning, so SIGHUP doesn't terminate the process
+    let mut hangup = signal(SignalKind::hangup())?;
+    actix_web::rt::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match Config::load_from(std::env::args_os()) {
+                Ok(config) => {
+                    app_state.reload(&config);
+                    log::info!("Configuration reloaded");
+                }
+                Err(err) => log::error!("Configuration not reloaded: {err}"),
+            }
+        }
+    });
+    Ok(())
 }
@@ -0,0 +1,220 @@
+use std::{
+    future::{ready, Ready},
+    sync::OnceLock,
+};
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderMap, HeaderName, HeaderValue},
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+use log::LevelFilter;
+use openidconnect::url::Url;
+use opentelemetry::{
+    global,
+    propagation::Extractor,
+    sdk::{propagation::TraceContextPropagator, trace, Resource},
+    trace::TraceError,
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use tracing::{field::Empty, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    filter, fmt::writer::BoxMakeWriter, layer::SubscriberExt, reload, util::SubscriberInitExt,
+    Layer, Registry,
+};
+use uuid::Uuid;
+
+use crate::{Config, LogFormat};
+
+/// Header carrying request correlation id.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Maximum length of request id accepted from clients.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Handle changing log level of the global subscriber.
+static LOG_LEVEL: OnceLock<reload::Handle<filter::LevelFilter, Registry>> = OnceLock::new();
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Returns id of the request currently being handled.
+#[must_use]
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+fn level_filter(level: LevelFilter) -> filter::LevelFilter {
+    match level {
+        LevelFilter::Off => filter::LevelFilter::OFF,
+        LevelFilter::Error => filter::LevelFilter::ERROR,
+        LevelFilter::Warn => filter::LevelFilter::WARN,
+        LevelFilter::Info => filter::LevelFilter::INFO,
+        LevelFilter::Debug => filter::LevelFilter::DEBUG,
+        LevelFilter::Trace => filter::LevelFilter::TRACE,
+    }
+}
+
+/// Install global OTLP/HTTP span exporter sending spans to `endpoint`.
+pub fn init_otlp_tracer(endpoint: &Url) -> Result<trace::Tracer, TraceError> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint.as_str()),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                "avanguard",
+            )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)
+}
+
+/// Initialize logging and, if OTLP endpoint is configured, span export.
+pub fn init_tracing(config: &Config) -> Result<(), TraceError> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let otel_layer = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            Some(tracing_opentelemetry::layer().with_tracer(init_otlp_tracer(endpoint)?))
+        }
+        None => None,
+    };
+    // Keep standard output of administrative commands for their results
+    let writer = if config.command.is_some() {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let fmt_layer = match config.log_format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().with_writer(writer).boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_writer(writer)
+            .boxed(),
+    };
+    let (level_layer, level_handle) = reload::Layer::new(level_filter(config.log_level));
+    let _ = LOG_LEVEL.set(level_handle);
+    tracing_subscriber::registry()
+        .with(level_layer)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .init();
+    Ok(())
+}
+
+/// Change log level of the subscriber installed with [`init_tracing`].
+pub fn set_log_level(level: LevelFilter) {
+    if let Some(handle) = LOG_LEVEL.get() {
+        match handle.reload(level_filter(level)) {
+            // Records from `log` macros are filtered by `log` crate first
+            Ok(()) => log::set_max_level(level),
+            Err(err) => log::error!("Failed to change log level: {err}"),
+        }
+    }
+}
+
+/// Flush and stop span export.
+pub fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+struct RequestHeaders<'a>(&'a HeaderMap);
+
+impl Extractor for RequestHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+/// Use request id sent by client if it looks sane, generate a new one otherwise.
+fn request_id(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LENGTH
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+        .map_or_else(|| Uuid::new_v4().to_string(), String::from)
+}
+
+/// Middleware wrapping each request in a tracing span and tagging it with `X-Request-Id`.
+pub struct RequestTracing;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTracing
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestTracingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestTracingMiddleware { service }))
+    }
+}
+
+pub struct RequestTracingMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTracingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = request_id(req.headers());
+        let span = tracing::info_span!(
+            "request",
+            method = %req.method(),
+            path = req.path(),
+            request_id = %request_id,
+            status = Empty,
+            otel.kind = "server",
+        );
+        // Continue trace started by the caller, if any
+        let parent_context = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&RequestHeaders(req.headers()))
+        });
+        span.set_parent(parent_context);
+
+        let future =
+            span.in_scope(|| REQUEST_ID.sync_scope(request_id.clone(), || self.service.call(req)));
+        let scoped_request_id = request_id.clone();
+        let response = async move {
+            let mut response = future.await?;
+            tracing::Span::current().record("status", response.status().as_u16());
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                response
+                    .headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(response)
+        };
+        Box::pin(REQUEST_ID.scope(scoped_request_id, response.instrument(span)))
+    }
+}
@@ -0,0 +1,300 @@
+use std::time::Duration as StdDuration;
+
+use actix_web::http::header::CONTENT_TYPE;
+use chrono::{Duration, NaiveDateTime, Utc};
+use futures_util::future::join_all;
+use model_derive::Model;
+use sqlx::{query, query_as, PgExecutor};
+use tracing::instrument;
+
+use crate::{crypto::hmac_sha256, db::DbPool, hex::to_lower_hex, random::gen_hex, Config};
+
+/// Header with name of the delivered event.
+pub const EVENT_HEADER: &str = "x-avanguard-event";
+
+/// Header with id of the delivery, the same for all attempts to deliver given event.
+pub const DELIVERY_HEADER: &str = "x-avanguard-delivery";
+
+/// Header with `sha256=<hex>` HMAC-SHA256 of request body keyed with webhook secret.
+pub const SIGNATURE_HEADER: &str = "x-avanguard-signature";
+
+/// Number of random bytes in generated webhook secret.
+const SECRET_BYTES: usize = 32;
+
+/// Maximum number of deliveries sent at once.
+const DELIVERY_BATCH_SIZE: i64 = 100;
+
+/// Webhook subscription. Empty `events` list subscribes to all events.
+#[derive(Model, Serialize)]
+pub struct Webhook {
+    pub(crate) id: Option<i64>,
+    pub url: String,
+    #[model(ref)]
+    pub events: Vec<String>,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub enabled: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl Webhook {
+    /// Create webhook with randomly generated signing secret.
+    #[must_use]
+    pub fn new(url: String, events: Vec<String>) -> Self {
+        Self {
+            id: None,
+            url,
+            events,
+            secret: gen_hex(SECRET_BYTES),
+            enabled: true,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// Queue event `payload` for delivery to enabled webhooks subscribed to `event`.
+/// Returns number of queued deliveries.
+#[instrument(skip_all, fields(event = event))]
+pub async fn enqueue<'e, E: PgExecutor<'e>>(
+    executor: E,
+    event: &str,
+    payload: &str,
+) -> Result<u64, sqlx::Error> {
+    let result = query!(
+        "INSERT INTO webhook_delivery (webhook_id, event, payload, created_at, next_attempt_at) \
+        SELECT id, $1, $2, $3, $3 FROM webhook \
+        WHERE enabled AND (cardinality(events) = 0 OR $1 = ANY(events))",
+        event,
+        payload,
+        Utc::now().naive_utc(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Delete up to `limit` deliveries which were delivered or abandoned before `cutoff`.
+/// Returns number of deleted deliveries.
+#[instrument(skip_all)]
+pub async fn delete_finished(
+    pool: &DbPool,
+    cutoff: NaiveDateTime,
+    limit: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = query!(
+        "DELETE FROM webhook_delivery WHERE id IN (SELECT id FROM webhook_delivery \
+        WHERE delivered_at < $1 OR failed_at < $1 LIMIT $2)",
+        cutoff,
+        limit
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Compute value of `X-Avanguard-Signature` header for request body.
+#[must_use]
+pub fn signature(secret: &str, payload: &str) -> String {
+    format!(
+        "sha256={}",
+        to_lower_hex(&hmac_sha256(secret.as_bytes(), payload.as_bytes()))
+    )
+}
+
+struct PendingDelivery {
+    id: i64,
+    event: String,
+    payload: String,
+    attempts: i32,
+    url: String,
+    secret: String,
+}
+
+/// Sends queued webhook deliveries, retrying failed ones with exponential backoff.
+pub struct WebhookSender {
+    client: reqwest::Client,
+    timeout: u32,
+    max_attempts: u32,
+    retry_delay: u32,
+    max_retry_delay: u32,
+}
+
+impl WebhookSender {
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(StdDuration::from_secs(config.webhook_timeout.into()))
+            .build()
+            .expect("Failed to build HTTP client");
+        Self {
+            client,
+            timeout: config.webhook_timeout,
+            max_attempts: config.webhook_max_attempts.max(1),
+            retry_delay: config.webhook_retry_delay,
+            max_retry_delay: config.webhook_max_retry_delay,
+        }
+    }
+
+    /// Returns delay in seconds before next attempt after given number of failed attempts.
+    #[must_use]
+    pub fn retry_seconds(&self, attempts: i32) -> u32 {
+        let exponent = u32::try_from(attempts - 1).unwrap_or_default().min(32);
+        let seconds = u64::from(self.retry_delay) << exponent;
+        seconds.min(self.max_retry_delay.into()) as u32
+    }
+
+    /// Send deliveries which are due. Returns number of attempted deliveries.
+    #[instrument(skip_all)]
+    pub async fn deliver_pending(&self, pool: &DbPool) -> Result<usize, sqlx::Error> {
+        let now = Utc::now().naive_utc();
+        // Postpone claimed deliveries, so other instances don't send them at the same time
+        let lease = now + Duration::seconds(2 * i64::from(self.timeout));
+        let deliveries = query_as!(
+            PendingDelivery,
+            "UPDATE webhook_delivery SET next_attempt_at = $2 FROM webhook \
+            WHERE webhook.id = webhook_delivery.webhook_id AND webhook_delivery.id IN ( \
+                SELECT webhook_delivery.id FROM webhook_delivery \
+                JOIN webhook ON webhook.id = webhook_delivery.webhook_id \
+                WHERE webhook.enabled AND delivered_at IS NULL AND failed_at IS NULL \
+                AND next_attempt_at <= $1 ORDER BY next_attempt_at LIMIT $3 \
+                FOR UPDATE OF webhook_delivery SKIP LOCKED) \
+            RETURNING webhook_delivery.id, webhook_delivery.event, webhook_delivery.payload, \
+            webhook_delivery.attempts, webhook.url, webhook.secret",
+            now,
+            lease,
+            DELIVERY_BATCH_SIZE,
+        )
+        .fetch_all(pool)
+        .await?;
+        let results = join_all(deliveries.iter().map(|delivery| self.send(delivery))).await;
+        for (delivery, result) in deliveries.iter().zip(results) {
+            self.finish(pool, delivery, result).await?;
+        }
+        Ok(deliveries.len())
+    }
+
+    async fn send(&self, delivery: &PendingDelivery) -> Result<(), String> {
+        let response = self
+            .client
+            .post(&delivery.url)
+            .header(CONTENT_TYPE.as_str(), "application/json")
+            .header(EVENT_HEADER, &delivery.event)
+            .header(DELIVERY_HEADER, delivery.id.to_string())
+            .header(
+                SIGNATURE_HEADER,
+                signature(&delivery.secret, &delivery.payload),
+            )
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("HTTP status {}", response.status()))
+        }
+    }
+
+    /// Mark delivery as delivered, or schedule retry or abandon it if sending failed.
+    async fn finish(
+        &self,
+        pool: &DbPool,
+        delivery: &PendingDelivery,
+        result: Result<(), String>,
+    ) -> Result<(), sqlx::Error> {
+        let now = Utc::now().naive_utc();
+        let attempts = delivery.attempts + 1;
+        match result {
+            Ok(()) => {
+                log::debug!(
+                    "Delivered webhook event {} with id {}",
+                    delivery.event,
+                    delivery.id
+                );
+                query!(
+                    "UPDATE webhook_delivery SET attempts = $2, delivered_at = $3, \
+                    last_error = NULL WHERE id = $1",
+                    delivery.id,
+                    attempts,
+                    now
+                )
+                .execute(pool)
+                .await?;
+            }
+            Err(err) if attempts >= self.max_attempts as i32 => {
+                log::warn!(
+                    "Abandoning webhook delivery {} to {} after {attempts} attempts: {err}",
+                    delivery.id,
+                    delivery.url,
+                );
+                query!(
+                    "UPDATE webhook_delivery SET attempts = $2, failed_at = $3, \
+                    last_error = $4 WHERE id = $1",
+                    delivery.id,
+                    attempts,
+                    now,
+                    err
+                )
+                .execute(pool)
+                .await?;
+            }
+            Err(err) => {
+                let retry_seconds = self.retry_seconds(attempts);
+                log::info!(
+                    "Webhook delivery {} to {} failed, retrying in {retry_seconds} seconds: {err}",
+                    delivery.id,
+                    delivery.url,
+                );
+                query!(
+                    "UPDATE webhook_delivery SET attempts = $2, next_attempt_at = $3, \
+                    last_error = $4 WHERE id = $1",
+                    delivery.id,
+                    attempts,
+                    now + Duration::seconds(retry_seconds.into()),
+                    err
+                )
+                .execute(pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Send pending webhook deliveries periodically, every `webhook_poll_interval` seconds.
+pub async fn run_webhook_task(pool: DbPool, config: Config) {
+    if config.webhook_poll_interval == 0 {
+        log::info!("Webhook delivery disabled");
+        return;
+    }
+    let sender = WebhookSender::new(&config);
+    let mut interval =
+        actix_web::rt::time::interval(StdDuration::from_secs(config.webhook_poll_interval.into()));
+    loop {
+        interval.tick().await;
+        if let Err(err) = sender.deliver_pending(&pool).await {
+            log::error!("Webhook delivery failed: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn test_retry_seconds() {
+        let mut config = Config::parse_from(["avanguard"]);
+        config.webhook_retry_delay = 10;
+        config.webhook_max_retry_delay = 60;
+        let sender = WebhookSender::new(&config);
+
+        assert_eq!(sender.retry_seconds(1), 10);
+        assert_eq!(sender.retry_seconds(2), 20);
+        assert_eq!(sender.retry_seconds(3), 40);
+        assert_eq!(sender.retry_seconds(4), 60);
+        assert_eq!(sender.retry_seconds(100), 60);
+    }
+}
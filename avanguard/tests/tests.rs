@@ -1,18 +1,50 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration as StdDuration,
+};
+
 use actix_web::{http, middleware, test, web, App};
+use async_trait::async_trait;
 use avanguard::{
+    audit::AuditTarget,
+    claims::{ClaimsError, ClaimsProvider, ClaimsRequest, StaticClaims},
+    cleanup::{run_cleanup, CleanupReport},
+    cli::{
+        run_command, ClaimsCommand, CliError, ClientCommand, Command, DbCommand, GroupCommand,
+        KeysCommand, OutputFormat, SecretName, TokenCommand, WalletCommand,
+    },
     config_service,
+    cors::cors,
     crypto::keccak256,
-    db::{init_db, DbPool, RefreshToken, Wallet},
+    db::{
+        connect_db, init_db, is_unavailable, migration_status, pending_migrations, Client, DbPool,
+        RefreshToken, Wallet,
+    },
+    events::{
+        connect_sink, emit, publish_pending, AuthEvent, EventSink, PublishError, EVENT_VERSION,
+    },
     hex::to_lower_hex,
+    metrics::RequestMetrics,
+    secrets::{load_secrets, refresh_secrets, SecretProvider, VaultProvider},
+    server::{on_connect, tls_config},
     state::AppState,
-    Challenge, Config, JwtToken, WalletAddress, WalletSignature, CHALLENGE_TEMPLATE,
+    telemetry::{init_otlp_tracer, shutdown_tracing, RequestTracing, REQUEST_ID_HEADER},
+    webhook::{self, WebhookSender, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER},
+    Avanguard, Challenge, Config, ConfigError, JwtToken, RateLimitBackend, WalletAddress,
+    WalletSignature, CHALLENGE_TEMPLATE,
 };
+use chrono::{Duration, NaiveDate, Utc};
 use clap::Parser;
 use ethers_core::types::transaction::eip712::{Eip712, TypedData};
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
-use secp256k1::{rand::rngs::OsRng, Message, Secp256k1};
+use openidconnect::url::Url;
+use secp256k1::{rand::rngs::OsRng, Message, Secp256k1, SecretKey};
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgConnectOptions, query, types::Uuid};
+use sqlx::{postgres::PgConnectOptions, query, query_scalar, types::Uuid};
+use tracing_subscriber::layer::SubscriberExt;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -51,11 +83,34 @@ async fn init_test_db() -> (DbPool, Config) {
         &db_name,
         &config.db_user,
         &config.db_password,
+        0,
     )
-    .await;
+    .await
+    .expect("Failed to initialize test database");
     (pool, config)
 }
 
+/// Generates wallet key pair, returns secret key and wallet address.
+fn generate_wallet() -> (SecretKey, String) {
+    let (secret_key, public_key) = Secp256k1::new().generate_keypair(&mut OsRng);
+    let public_key = public_key.serialize_uncompressed();
+    let hash = keccak256(&public_key[1..]);
+    (secret_key, to_lower_hex(&hash[hash.len() - 20..]))
+}
+
+/// Signs EIP-712 challenge message, returns hex-encoded recoverable signature.
+fn sign_challenge(secret_key: &SecretKey, challenge: &str) -> String {
+    let typed_data: TypedData = serde_json::from_str(challenge).unwrap();
+    let message = Message::from_slice(&typed_data.encode_eip712().unwrap()).unwrap();
+    let (rec_id, sig) = Secp256k1::new()
+        .sign_ecdsa_recoverable(&message, secret_key)
+        .serialize_compact();
+    let mut sig_arr = [0; 65];
+    sig_arr[0..64].copy_from_slice(&sig);
+    sig_arr[64] = rec_id.to_i32() as u8;
+    to_lower_hex(&sig_arr)
+}
+
 #[actix_web::test]
 async fn test_challenge_signing() {
     // Create wallet public & private keys
@@ -72,7 +127,9 @@ async fn test_challenge_signing() {
     let (pool, config) = init_test_db().await;
     let app = test::init_service(
         App::new()
-            .app_data(web::Data::new(AppState::new(config.clone(), pool.clone())))
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
             .wrap(middleware::Logger::default())
             .configure(config_service),
     )
@@ -137,6 +194,8 @@ async fn test_challenge_signing() {
             address: wallet_address.clone(),
             signature: to_lower_hex(&sig_arr),
             nonce: String::from("test"),
+            client_id: None,
+            scope: String::new(),
         })
         .to_request();
     let token: JwtToken = test::call_and_read_body_json(&app, request).await;
@@ -177,6 +236,12 @@ async fn test_challenge_signing() {
     // Check if token has used_at set
     let refresh_token = RefreshToken::find_by_id(&pool, 1).await.unwrap().unwrap();
     assert!(refresh_token.used_at.is_some());
+    // Only keyed hash of the token is stored
+    assert_ne!(refresh_token.token_hash, token.refresh_token);
+    assert_eq!(
+        refresh_token.token_hash,
+        RefreshToken::hash_token(config.refresh_token_secret.as_bytes(), &token.refresh_token)
+    );
 
     // Test refreshing with new token
     let request = test::TestRequest::post()
@@ -196,3 +261,2728 @@ async fn test_challenge_signing() {
     let refresh_token = RefreshToken::find_by_id(&pool, 2).await.unwrap().unwrap();
     assert!(refresh_token.used_at.is_some());
 }
+
+#[actix_web::test]
+async fn test_cleanup() {
+    let (pool, config) = init_test_db().await;
+    let secret = config.refresh_token_secret.as_bytes();
+    let long_ago = (Utc::now() - Duration::days(30)).naive_utc();
+
+    // Unverified wallet past retention period
+    let mut stale_wallet = Wallet::new("0x01".into());
+    stale_wallet.creation_timestamp = long_ago;
+    stale_wallet.save(&pool).await.unwrap();
+    // Recently created unverified wallet
+    let mut new_wallet = Wallet::new("0x02".into());
+    new_wallet.save(&pool).await.unwrap();
+    // Verified wallet with refresh tokens
+    let mut wallet = Wallet::new("0x03".into());
+    wallet.creation_timestamp = long_ago;
+    wallet.save(&pool).await.unwrap();
+    wallet.set_signature(&pool, "0xsignature").await.unwrap();
+    let wallet_id: i64 = query_scalar("SELECT id FROM wallet WHERE address = '0x03'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    let (mut expired_token, _) = RefreshToken::new(wallet_id, None, 0, secret);
+    expired_token.expires_at = long_ago;
+    expired_token.save(&pool).await.unwrap();
+    let (mut used_token, _) = RefreshToken::new(wallet_id, None, 3600, secret);
+    used_token.used_at = Some(long_ago);
+    used_token.save(&pool).await.unwrap();
+    let (mut blacklisted_token, _) = RefreshToken::new(wallet_id, None, 3600, secret);
+    blacklisted_token.blacklisted_at = Some(long_ago);
+    blacklisted_token.save(&pool).await.unwrap();
+    let (mut valid_token, _) = RefreshToken::new(wallet_id, None, 3600, secret);
+    valid_token.save(&pool).await.unwrap();
+
+    let report = run_cleanup(&pool, &config).await.unwrap();
+    assert_eq!(
+        report,
+        Some(CleanupReport {
+            expired_tokens: 1,
+            revoked_tokens: 2,
+            unverified_wallets: 1,
+            login_events: 0,
+            webhook_deliveries: 0,
+            published_events: 0,
+            rate_limits: 0,
+            failed_login_ips: 0,
+        })
+    );
+    assert!(Wallet::find_by_address(&pool, "0x01")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(Wallet::find_by_address(&pool, "0x02")
+        .await
+        .unwrap()
+        .is_some());
+    assert!(Wallet::find_by_address(&pool, "0x03")
+        .await
+        .unwrap()
+        .is_some());
+    assert_eq!(RefreshToken::all(&pool).await.unwrap().len(), 1);
+
+    // Nothing left to delete
+    let report = run_cleanup(&pool, &config).await.unwrap();
+    assert_eq!(report, Some(CleanupReport::default()));
+}
+
+#[actix_web::test]
+async fn test_rate_limit() {
+    for backend in [RateLimitBackend::Memory, RateLimitBackend::Postgres] {
+        let (pool, mut config) = init_test_db().await;
+        config.rate_limit_backend = backend;
+        config.rate_limit_ip = 3;
+        config.rate_limit_address = 2;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(
+                    AppState::new(config.clone(), pool.clone()).unwrap(),
+                ))
+                .configure(config_service),
+        )
+        .await;
+
+        let start = |ip: &str, address: &str| {
+            test::TestRequest::post()
+                .uri("/auth/start")
+                .peer_addr(format!("{ip}:1234").parse().unwrap())
+                .set_json(WalletAddress {
+                    address: address.into(),
+                })
+                .to_request()
+        };
+
+        // Address limit
+        for _ in 0..2 {
+            let response = test::call_service(&app, start("10.0.0.1", "0x01")).await;
+            assert_eq!(response.status(), http::StatusCode::OK);
+        }
+        let response = test::call_service(&app, start("10.0.0.2", "0x01")).await;
+        assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response
+            .headers()
+            .get(http::header::RETRY_AFTER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(retry_after > 0 && retry_after <= u64::from(config.rate_limit_window));
+
+        // IP limit, 10.0.0.1 already made 2 requests
+        let response = test::call_service(&app, start("10.0.0.1", "0x02")).await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let response = test::call_service(&app, start("10.0.0.1", "0x03")).await;
+        assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+
+        // Address limit of refresh, counted for the wallet of the token
+        Wallet::new("0x04".into()).save(&pool).await.unwrap();
+        let wallet_id: i64 = query_scalar("SELECT id FROM wallet WHERE address = '0x04'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        let secret = config.refresh_token_secret.as_bytes();
+        let mut tokens = Vec::new();
+        for _ in 0..3 {
+            let (mut refresh_token, token) = RefreshToken::new(wallet_id, None, 3600, secret);
+            refresh_token.save(&pool).await.unwrap();
+            tokens.push(token);
+        }
+        for (i, token) in tokens.into_iter().enumerate() {
+            let response = test::call_service(
+                &app,
+                test::TestRequest::post()
+                    .uri("/refresh")
+                    .peer_addr(format!("10.0.1.{i}:1234").parse().unwrap())
+                    .set_json(RefreshTokenRequest {
+                        refresh_token: token,
+                    })
+                    .to_request(),
+            )
+            .await;
+            let expected = if i < 2 {
+                http::StatusCode::OK
+            } else {
+                http::StatusCode::TOO_MANY_REQUESTS
+            };
+            assert_eq!(response.status(), expected);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_failed_login_lockout() {
+    let (pool, mut config) = init_test_db().await;
+    config.lockout_threshold = 2;
+    config.admin_token = Some("admin".into());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .configure(config_service),
+    )
+    .await;
+    // Signatures of the challenges made by another wallet
+    let (other_key, _) = generate_wallet();
+    let mut wallets = Vec::new();
+    for _ in 0..4 {
+        let (_, address) = generate_wallet();
+        let mut wallet = Wallet::new(address.clone());
+        wallet.save(&pool).await.unwrap();
+        wallets.push((
+            address,
+            sign_challenge(&other_key, &wallet.challenge_message),
+        ));
+    }
+    let address = wallets[0].0.as_str();
+
+    let login_with = |ip: Option<&str>, wallet: usize, signature: Option<&str>| {
+        let (address, other_signature) = &wallets[wallet];
+        let request = test::TestRequest::post()
+            .uri("/auth")
+            .set_json(WalletSignature {
+                address: address.clone(),
+                signature: signature.unwrap_or(other_signature).into(),
+                nonce: "test".into(),
+                client_id: None,
+                scope: String::new(),
+            });
+        match ip {
+            Some(ip) => request.peer_addr(format!("{ip}:1234").parse().unwrap()),
+            None => request,
+        }
+        .to_request()
+    };
+    let login = |ip: Option<&str>, wallet: usize| login_with(ip, wallet, None);
+
+    // Malformed signatures don't count towards lockout
+    for signature in ["0x00", "0xzz", "0x00"] {
+        let response = test::call_service(&app, login_with(None, 3, Some(signature))).await;
+        assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    }
+    let wallet = Wallet::find_by_address(&pool, &wallets[3].0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(wallet.failed_login_attempts, 0);
+
+    // Wallet lockout
+    for _ in 0..2 {
+        let response = test::call_service(&app, login(None, 0)).await;
+        assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    }
+    let response = test::call_service(&app, login(None, 0)).await;
+    assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key(http::header::RETRY_AFTER));
+    let error: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(error["error"], "WalletLocked");
+    assert!(error["locked_until"].is_string());
+    let wallet = Wallet::find_by_address(&pool, address)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(wallet.failed_login_attempts, 2);
+    assert!(wallet.locked_until().is_some());
+
+    // Clearing lockout requires admin token
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/wallet/{address}/lockout"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/wallet/{address}/lockout"))
+        .insert_header((http::header::AUTHORIZATION, "Bearer admin"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    let wallet = Wallet::find_by_address(&pool, address)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(wallet.failed_login_attempts, 0);
+    assert!(wallet.locked_until().is_none());
+    let response = test::call_service(&app, login(None, 0)).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+    // Counter starts over after the longest lockout
+    let long_ago =
+        (Utc::now() - Duration::seconds(config.lockout_max_duration.into()) - Duration::minutes(1))
+            .naive_utc();
+    query("UPDATE wallet SET last_failed_login_at = $1 WHERE address = $2")
+        .bind(long_ago)
+        .bind(address)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let response = test::call_service(&app, login(None, 0)).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let wallet = Wallet::find_by_address(&pool, address)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(wallet.failed_login_attempts, 1);
+    assert!(wallet.locked_until().is_none());
+
+    // IP lockout applies to all wallets
+    let response = test::call_service(&app, login(Some("10.0.0.1"), 1)).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login(Some("10.0.0.1"), 2)).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login(Some("10.0.0.1"), 2)).await;
+    assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    let response = test::call_service(&app, login(Some("10.0.0.2"), 2)).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_metrics() {
+    let (pool, config) = init_test_db().await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .wrap(RequestMetrics)
+            .configure(config_service),
+    )
+    .await;
+
+    let request = test::TestRequest::post()
+        .uri("/auth/start")
+        .set_json(WalletAddress {
+            address: "0x01".into(),
+        })
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    let request = test::TestRequest::post()
+        .uri("/auth")
+        .set_json(WalletSignature {
+            address: "0x02".into(),
+            signature: "0x00".into(),
+            nonce: "test".into(),
+            client_id: None,
+            scope: String::new(),
+        })
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+    let request = test::TestRequest::get().uri("/metrics").to_request();
+    let body = test::call_and_read_body(&app, request).await;
+    let metrics = String::from_utf8(body.to_vec()).unwrap();
+    assert!(metrics.contains("avanguard_challenges_total{outcome=\"Success\"} 1"));
+    assert!(metrics.contains("avanguard_logins_total{outcome=\"WalletNotFound\"} 1"));
+    assert!(metrics.contains(
+        "avanguard_http_request_duration_seconds_count{method=\"POST\",route=\"/auth/start\",status=\"200\"} 1"
+    ));
+    assert!(metrics.contains("avanguard_db_pool_connections"));
+}
+
+#[actix_web::test]
+async fn test_request_id() {
+    let (pool, config) = init_test_db().await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .wrap(RequestTracing)
+            .configure(config_service),
+    )
+    .await;
+
+    // Request id is generated and returned in header and error body
+    let request = test::TestRequest::post()
+        .uri("/refresh")
+        .set_json(RefreshTokenRequest {
+            refresh_token: "invalid".into(),
+        })
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let request_id = response
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(Uuid::parse_str(&request_id).is_ok());
+    let error: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(error["request_id"], request_id.as_str());
+
+    // Request id sent by client is propagated
+    let request = test::TestRequest::get()
+        .uri("/api/health")
+        .insert_header((REQUEST_ID_HEADER, "client-request.1"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(
+        response.headers().get(REQUEST_ID_HEADER).unwrap(),
+        "client-request.1"
+    );
+
+    // Malformed request id is replaced
+    let request = test::TestRequest::get()
+        .uri("/api/health")
+        .insert_header((REQUEST_ID_HEADER, "bad id"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_ne!(response.headers().get(REQUEST_ID_HEADER).unwrap(), "bad id");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_otlp_export() {
+    // Collector stand-in accepting a single export request
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = Url::parse(&format!(
+        "http://{}/v1/traces",
+        listener.local_addr().unwrap()
+    ))
+    .unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(StdDuration::from_millis(500)))
+            .unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        while let Ok(read) = stream.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .unwrap();
+        tx.send(request).unwrap();
+    });
+
+    let tracer = init_otlp_tracer(&endpoint).unwrap();
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info_span!("test_span").in_scope(|| tracing::info!("exported"));
+    });
+    tokio::task::spawn_blocking(shutdown_tracing).await.unwrap();
+
+    let request = rx.recv_timeout(StdDuration::from_secs(10)).unwrap();
+    let request = String::from_utf8_lossy(&request);
+    assert!(request.starts_with("POST /v1/traces"));
+    assert!(request.contains("application/x-protobuf"));
+    assert!(request.contains("test_span"));
+}
+
+#[actix_web::test]
+async fn test_audit_log() {
+    let (pool, mut config) = init_test_db().await;
+    let path = std::env::temp_dir().join(format!("avanguard-audit-{}.log", Uuid::new_v4()));
+    config.audit_log = Some(AuditTarget::File(path.clone()));
+    config.lockout_threshold = 1;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .configure(config_service),
+    )
+    .await;
+
+    let (_, address) = generate_wallet();
+    let (other_key, _) = generate_wallet();
+    let request = test::TestRequest::post()
+        .uri("/auth/start")
+        .peer_addr("10.0.0.1:1234".parse().unwrap())
+        .insert_header((http::header::USER_AGENT, "test-agent"))
+        .set_json(WalletAddress {
+            address: address.clone(),
+        })
+        .to_request();
+    let challenge: Challenge = test::call_and_read_body_json(&app, request).await;
+    let request = test::TestRequest::post()
+        .uri("/auth")
+        .set_json(WalletSignature {
+            address: address.clone(),
+            signature: sign_challenge(&other_key, &challenge.challenge),
+            nonce: "test".into(),
+            client_id: None,
+            scope: String::new(),
+        })
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+    let events: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0]["event"], "challenge_issued");
+    assert_eq!(events[0]["wallet"], address.as_str());
+    assert_eq!(events[0]["client_id"], config.client_id.as_str());
+    assert_eq!(events[0]["ip"], "10.0.0.1");
+    assert_eq!(events[0]["user_agent"], "test-agent");
+    assert_eq!(events[1]["event"], "wallet_locked");
+    assert!(events[1]["locked_until"].is_string());
+    assert_eq!(events[2]["event"], "login_failed");
+    assert_eq!(events[2]["reason"], "SignerMismatch");
+}
+
+#[actix_web::test]
+async fn test_login_history() {
+    let (pool, mut config) = init_test_db().await;
+    config.admin_token = Some("admin".into());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .configure(config_service),
+    )
+    .await;
+    let (secret_key, address) = generate_wallet();
+
+    let request = test::TestRequest::post()
+        .uri("/auth/start")
+        .set_json(WalletAddress {
+            address: address.clone(),
+        })
+        .to_request();
+    let challenge: Challenge = test::call_and_read_body_json(&app, request).await;
+    let login = |signature: String| {
+        test::TestRequest::post()
+            .uri("/auth")
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .insert_header((http::header::USER_AGENT, "test-agent"))
+            .set_json(WalletSignature {
+                address: address.clone(),
+                signature,
+                nonce: "test".into(),
+                client_id: None,
+                scope: String::new(),
+            })
+            .to_request()
+    };
+    let response = test::call_service(&app, login("0x00".into())).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let signature = sign_challenge(&secret_key, &challenge.challenge);
+    let first: JwtToken = test::call_and_read_body_json(&app, login(signature.clone())).await;
+    let second: JwtToken = test::call_and_read_body_json(&app, login(signature)).await;
+
+    // Refreshed token stays in the family of the login which created it
+    let request = test::TestRequest::post()
+        .uri("/refresh")
+        .set_json(RefreshTokenRequest {
+            refresh_token: first.refresh_token,
+        })
+        .to_request();
+    let _: JwtToken = test::call_and_read_body_json(&app, request).await;
+    let secret = config.refresh_token_secret.as_bytes();
+    let second_token = RefreshToken::find_refresh_token(&pool, secret, &second.refresh_token)
+        .await
+        .unwrap()
+        .unwrap();
+    for token in RefreshToken::all(&pool).await.unwrap() {
+        assert!(token.login_event_id.is_some());
+    }
+
+    // Admin API requires admin token
+    let request = test::TestRequest::get()
+        .uri(&format!("/api/wallet/{address}/logins"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+    let request = test::TestRequest::get()
+        .uri(&format!("/api/wallet/{address}/logins"))
+        .insert_header((http::header::AUTHORIZATION, "Bearer admin"))
+        .to_request();
+    let logins: Vec<serde_json::Value> = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(logins.len(), 3);
+    assert_eq!(logins[0]["outcome"], "Success");
+    assert_eq!(logins[0]["id"], second_token.login_event_id.unwrap());
+    assert_eq!(logins[1]["outcome"], "Success");
+    assert_eq!(logins[2]["outcome"], "UnsupportedSignatureFormat");
+    for login in &logins {
+        assert_eq!(login["ip"], "10.0.0.1");
+        assert_eq!(login["user_agent"], "test-agent");
+        assert_eq!(login["client_id"], config.client_id.as_str());
+        assert_eq!(login["method"], "eip712");
+    }
+    let request = test::TestRequest::get()
+        .uri(&format!("/api/wallet/{address}/logins?limit=1&offset=2"))
+        .insert_header((http::header::AUTHORIZATION, "Bearer admin"))
+        .to_request();
+    let logins: Vec<serde_json::Value> = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(logins.len(), 1);
+    assert_eq!(logins[0]["outcome"], "UnsupportedSignatureFormat");
+
+    // Both logins have an active session
+    let request = test::TestRequest::get()
+        .uri(&format!("/api/wallet/{address}/sessions"))
+        .insert_header((http::header::AUTHORIZATION, "Bearer admin"))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(
+        sessions[0]["login_event_id"],
+        second_token.login_event_id.unwrap()
+    );
+    second_token.blacklist(&pool).await.unwrap();
+    let request = test::TestRequest::get()
+        .uri(&format!("/api/wallet/{address}/sessions"))
+        .insert_header((http::header::AUTHORIZATION, "Bearer admin"))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(sessions.len(), 1);
+
+    let request = test::TestRequest::get()
+        .uri("/api/wallet/0x01/sessions")
+        .insert_header((http::header::AUTHORIZATION, "Bearer admin"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_self_service_sessions() {
+    let (pool, config) = init_test_db().await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .configure(config_service),
+    )
+    .await;
+    let (secret_key, address) = generate_wallet();
+    let request = test::TestRequest::post()
+        .uri("/auth/start")
+        .set_json(WalletAddress {
+            address: address.clone(),
+        })
+        .to_request();
+    let challenge: Challenge = test::call_and_read_body_json(&app, request).await;
+    let signature = sign_challenge(&secret_key, &challenge.challenge);
+    let mut tokens = Vec::new();
+    for _ in 0..3 {
+        let request = test::TestRequest::post()
+            .uri("/auth")
+            .set_json(WalletSignature {
+                address: address.clone(),
+                signature: signature.clone(),
+                nonce: "test".into(),
+                client_id: None,
+                scope: String::new(),
+            })
+            .to_request();
+        let token: JwtToken = test::call_and_read_body_json(&app, request).await;
+        tokens.push(token);
+    }
+    let bearer = format!("Bearer {}", tokens[0].token);
+    let refresh = |refresh_token: &str| {
+        test::TestRequest::post()
+            .uri("/refresh")
+            .set_json(RefreshTokenRequest {
+                refresh_token: refresh_token.into(),
+            })
+            .to_request()
+    };
+
+    // Bearer id token is required
+    let request = test::TestRequest::get().uri("/api/session").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let request = test::TestRequest::get()
+        .uri("/api/session")
+        .insert_header((http::header::AUTHORIZATION, "Bearer invalid"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+    let request = test::TestRequest::get()
+        .uri("/api/session")
+        .insert_header((http::header::AUTHORIZATION, bearer.as_str()))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(sessions.len(), 3);
+
+    // Revoke the most recent session
+    let id = sessions[0]["login_event_id"].as_i64().unwrap();
+    let mut looked_up = RefreshToken::find_refresh_token(
+        &pool,
+        config.refresh_token_secret.as_bytes(),
+        &tokens[2].refresh_token,
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/session/{id}"))
+        .insert_header((http::header::AUTHORIZATION, bearer.as_str()))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    // Refresh which looked the token up before revocation can't use it
+    assert!(!looked_up.set_used(&pool).await.unwrap());
+    let response = test::call_service(&app, refresh(&tokens[2].refresh_token)).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/session/{id}"))
+        .insert_header((http::header::AUTHORIZATION, bearer.as_str()))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+
+    // Sessions of other wallets can't be revoked
+    let mut other = Wallet::new("0x01".into());
+    other.save(&pool).await.unwrap();
+    let other_id: i64 = query_scalar("SELECT id FROM wallet WHERE address = '0x01'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let (mut other_token, _) = RefreshToken::new(other_id, Some(id), 3600, b"secret");
+    other_token.save(&pool).await.unwrap();
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/session/{id}"))
+        .insert_header((http::header::AUTHORIZATION, bearer.as_str()))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+
+    // Sessions without usable tokens can't be revoked, used and expired tokens aren't counted
+    let id = sessions[1]["login_event_id"].as_i64().unwrap();
+    let response = test::call_service(&app, refresh(&tokens[1].refresh_token)).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    query("UPDATE refreshtoken SET expires_at = $2 WHERE login_event_id = $1 AND used_at IS NULL")
+        .bind(id)
+        .bind((Utc::now() - Duration::minutes(1)).naive_utc())
+        .execute(&pool)
+        .await
+        .unwrap();
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/session/{id}"))
+        .insert_header((http::header::AUTHORIZATION, bearer.as_str()))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+
+    // Log out everywhere
+    let request = test::TestRequest::delete()
+        .uri("/api/session")
+        .insert_header((http::header::AUTHORIZATION, bearer.as_str()))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    for token in &tokens {
+        let response = test::call_service(&app, refresh(&token.refresh_token)).await;
+        assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    }
+    let request = test::TestRequest::get()
+        .uri("/api/session")
+        .insert_header((http::header::AUTHORIZATION, bearer.as_str()))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, request).await;
+    assert!(sessions.is_empty());
+
+    let request = test::TestRequest::get().uri("/metrics").to_request();
+    let metrics =
+        String::from_utf8(test::call_and_read_body(&app, request).await.to_vec()).unwrap();
+    assert!(metrics.contains("avanguard_revocations_total 2"));
+}
+
+/// HTTP server stand-in, responds to consecutive requests with given statuses and bodies.
+/// Returns server URL and channel with request heads and bodies.
+fn spawn_http_server(responses: Vec<(u16, String)>) -> (String, mpsc::Receiver<(String, String)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for (status, response) in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            let (head, body) = loop {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length: ")
+                                .map(String::from)
+                        })
+                        .map_or(0, |length| length.parse().unwrap());
+                    if body.len() >= length {
+                        break (head.to_lowercase(), body.to_string());
+                    }
+                }
+            };
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 {status} Status\r\ncontent-length: {}\r\n\
+                        connection: close\r\n\r\n{response}",
+                        response.len()
+                    )
+                    .as_bytes(),
+                )
+                .unwrap();
+            tx.send((head, body)).unwrap();
+        }
+    });
+    (url, rx)
+}
+
+/// Webhook receiver stand-in, responds to consecutive requests with given statuses.
+fn spawn_webhook_receiver(statuses: Vec<u16>) -> (String, mpsc::Receiver<(String, String)>) {
+    let (url, rx) = spawn_http_server(
+        statuses
+            .into_iter()
+            .map(|status| (status, String::new()))
+            .collect(),
+    );
+    (url + "hook", rx)
+}
+
+fn header_value<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines()
+        .find_map(|line| line.strip_prefix(&format!("{name}: ")))
+}
+
+#[actix_web::test]
+async fn test_webhooks() {
+    let (pool, mut config) = init_test_db().await;
+    config.admin_token = Some("admin".into());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .configure(config_service),
+    )
+    .await;
+    let (url, rx) = spawn_webhook_receiver(vec![500, 200]);
+    let create = |url: &str, events: Vec<&str>| {
+        test::TestRequest::post()
+            .uri("/api/webhook")
+            .insert_header((http::header::AUTHORIZATION, "Bearer admin"))
+            .set_json(serde_json::json!({ "url": url, "events": events }))
+            .to_request()
+    };
+
+    // Invalid subscriptions are rejected
+    let response = test::call_service(&app, create("ftp://localhost", vec![])).await;
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    let response = test::call_service(&app, create(&url, vec!["unknown"])).await;
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+    let webhook: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        create(&url, vec!["wallet.registered", "login.succeeded"]),
+    )
+    .await;
+    let secret = webhook["secret"].as_str().unwrap().to_string();
+    let other: serde_json::Value =
+        test::call_and_read_body_json(&app, create(&url, vec!["session.revoked"])).await;
+    let request = test::TestRequest::get()
+        .uri("/api/webhook")
+        .insert_header((http::header::AUTHORIZATION, "Bearer admin"))
+        .to_request();
+    let webhooks: Vec<serde_json::Value> = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(webhooks.len(), 2);
+    assert!(webhooks[0].get("secret").is_none());
+
+    // Only webhook subscribed to the event gets a delivery
+    let request = test::TestRequest::post()
+        .uri("/auth/start")
+        .set_json(WalletAddress {
+            address: "0x01".into(),
+        })
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    let queued: i64 = query_scalar("SELECT count(*) FROM webhook_delivery")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 1);
+
+    // Failed delivery is retried later
+    let sender = WebhookSender::new(&config);
+    assert_eq!(sender.deliver_pending(&pool).await.unwrap(), 1);
+    let (first_head, _) = rx.recv_timeout(StdDuration::from_secs(10)).unwrap();
+    let (attempts, last_error): (i32, Option<String>) =
+        sqlx::query_as("SELECT attempts, last_error FROM webhook_delivery")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(attempts, 1);
+    assert!(last_error.unwrap().contains("500"));
+    assert_eq!(sender.deliver_pending(&pool).await.unwrap(), 0);
+    query("UPDATE webhook_delivery SET next_attempt_at = now() - interval '1 second'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(sender.deliver_pending(&pool).await.unwrap(), 1);
+    let (head, body) = rx.recv_timeout(StdDuration::from_secs(10)).unwrap();
+    let delivered: bool = query_scalar("SELECT delivered_at IS NOT NULL FROM webhook_delivery")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(delivered);
+    assert_eq!(sender.deliver_pending(&pool).await.unwrap(), 0);
+
+    assert!(head.starts_with("post /hook"));
+    assert_eq!(header_value(&head, EVENT_HEADER), Some("wallet.registered"));
+    assert_eq!(
+        header_value(&head, DELIVERY_HEADER),
+        header_value(&first_head, DELIVERY_HEADER)
+    );
+    assert_eq!(
+        header_value(&head, SIGNATURE_HEADER),
+        Some(webhook::signature(&secret, &body).as_str())
+    );
+    let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payload["event"], "wallet.registered");
+    assert_eq!(payload["data"]["address"], "0x01");
+    assert!(payload["timestamp"].is_string());
+
+    // Delete webhooks
+    for webhook in [&webhook, &other] {
+        let request = test::TestRequest::delete()
+            .uri(&format!("/api/webhook/{}", webhook["id"]))
+            .insert_header((http::header::AUTHORIZATION, "Bearer admin"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    }
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/webhook/{}", webhook["id"]))
+        .insert_header((http::header::AUTHORIZATION, "Bearer admin"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+}
+
+/// Event sink collecting published events, failing after `fail_after` events.
+#[derive(Default)]
+struct CollectingSink {
+    events: Mutex<Vec<(String, String, String)>>,
+    fail_after: Option<usize>,
+}
+
+#[async_trait]
+impl EventSink for CollectingSink {
+    async fn publish(&self, event: &str, key: &str, payload: &str) -> Result<(), PublishError> {
+        let mut events = self.events.lock().unwrap();
+        if self.fail_after.is_some_and(|limit| events.len() >= limit) {
+            return Err(PublishError("broker unavailable".into()));
+        }
+        events.push((event.into(), key.into(), payload.into()));
+        Ok(())
+    }
+}
+
+async fn pending_events(pool: &DbPool) -> i64 {
+    query_scalar("SELECT count(*) FROM event_outbox WHERE published_at IS NULL")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn test_event_outbox() {
+    let (pool, mut config) = init_test_db().await;
+    assert!(
+        connect_sink(&Url::parse("amqp://localhost").unwrap(), "avanguard")
+            .await
+            .is_err()
+    );
+
+    // Events are not stored without event sink
+    let event = AuthEvent::WalletRegistered {
+        address: "0x01".into(),
+    };
+    let mut conn = pool.acquire().await.unwrap();
+    emit(&mut conn, &config, &event).await.unwrap();
+    assert_eq!(pending_events(&pool).await, 0);
+
+    // Events are stored only if the transaction is committed
+    config.event_sink = Some(Url::parse("nats://localhost").unwrap());
+    let mut transaction = pool.begin().await.unwrap();
+    emit(&mut transaction, &config, &event).await.unwrap();
+    transaction.rollback().await.unwrap();
+    assert_eq!(pending_events(&pool).await, 0);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .configure(config_service),
+    )
+    .await;
+    let (secret_key, address) = generate_wallet();
+    let request = test::TestRequest::post()
+        .uri("/auth/start")
+        .set_json(WalletAddress {
+            address: address.clone(),
+        })
+        .to_request();
+    let challenge: Challenge = test::call_and_read_body_json(&app, request).await;
+    let request = test::TestRequest::post()
+        .uri("/auth")
+        .set_json(WalletSignature {
+            address: address.clone(),
+            signature: sign_challenge(&secret_key, &challenge.challenge),
+            nonce: "nonce".into(),
+            client_id: None,
+            scope: String::new(),
+        })
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(pending_events(&pool).await, 2);
+
+    // Publishing stops at the first failure and resumes in order
+    let failing = CollectingSink {
+        fail_after: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(publish_pending(&pool, &failing).await.unwrap(), 1);
+    assert_eq!(pending_events(&pool).await, 1);
+    let sink = CollectingSink::default();
+    assert_eq!(publish_pending(&pool, &sink).await.unwrap(), 1);
+    assert_eq!(publish_pending(&pool, &sink).await.unwrap(), 0);
+    assert_eq!(pending_events(&pool).await, 0);
+
+    let published = failing.events.into_inner().unwrap();
+    assert_eq!(published[0].0, "wallet.registered");
+    let published = sink.events.into_inner().unwrap();
+    let (event, key, payload) = &published[0];
+    assert_eq!(event, "login.succeeded");
+    assert_eq!(key, &address);
+    let payload: serde_json::Value = serde_json::from_str(payload).unwrap();
+    assert_eq!(payload["version"], EVENT_VERSION);
+    assert_eq!(payload["event"], "login.succeeded");
+    assert_eq!(payload["data"]["address"], address);
+    assert!(payload["data"]["session_id"].is_i64());
+}
+
+/// Requires NATS server, e.g. `docker run -p 4222:4222 nats`
+/// and `AG_TEST_NATS_URL=nats://localhost:4222`.
+#[cfg(feature = "nats")]
+#[actix_web::test]
+#[ignore]
+async fn test_nats_sink() {
+    use futures_util::StreamExt;
+
+    let url = Url::parse(&std::env::var("AG_TEST_NATS_URL").unwrap()).unwrap();
+    let subscriber_client = async_nats::connect(format!(
+        "{}:{}",
+        url.host_str().unwrap(),
+        url.port().unwrap_or(4222)
+    ))
+    .await
+    .unwrap();
+    let mut subscriber = subscriber_client
+        .subscribe("avanguard-test.>".into())
+        .await
+        .unwrap();
+    subscriber_client.flush().await.unwrap();
+
+    let sink = connect_sink(&url, "avanguard-test").await.unwrap();
+    sink.publish("login.succeeded", "0x01", "{}").await.unwrap();
+    let message = subscriber.next().await.unwrap();
+    assert_eq!(message.subject, "avanguard-test.login.succeeded");
+    assert_eq!(message.payload.as_ref(), b"{}");
+}
+
+/// Requires Kafka broker, e.g. `docker run -p 9092:9092 apache/kafka`
+/// and `AG_TEST_KAFKA_URL=kafka://localhost:9092`.
+#[cfg(feature = "kafka")]
+#[actix_web::test]
+#[ignore]
+async fn test_kafka_sink() {
+    use rskafka::client::{partition::UnknownTopicHandling, ClientBuilder};
+
+    let url = Url::parse(&std::env::var("AG_TEST_KAFKA_URL").unwrap()).unwrap();
+    let topic = format!("avanguard-test-{}", Uuid::new_v4());
+    let client = ClientBuilder::new(vec![format!(
+        "{}:{}",
+        url.host_str().unwrap(),
+        url.port().unwrap_or(9092)
+    )])
+    .build()
+    .await
+    .unwrap();
+    client
+        .controller_client()
+        .unwrap()
+        .create_topic(&topic, 1, 1, 5_000)
+        .await
+        .unwrap();
+
+    let sink = connect_sink(&url, &topic).await.unwrap();
+    sink.publish("login.succeeded", "0x01", "{}").await.unwrap();
+    let partition = client
+        .partition_client(&topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
+    let (records, _) = partition
+        .fetch_records(0, 1..1_000_000, 5_000)
+        .await
+        .unwrap();
+    let record = &records[0].record;
+    assert_eq!(record.key.as_deref(), Some(b"0x01".as_slice()));
+    assert_eq!(record.value.as_deref(), Some(b"{}".as_slice()));
+    assert_eq!(record.headers["event"], b"login.succeeded");
+}
+
+#[actix_web::test]
+async fn test_config_reload() {
+    let (pool, config) = init_test_db().await;
+    let app_state = web::Data::new(AppState::new(config.clone(), pool).unwrap());
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state.clone())
+            .configure(config_service),
+    )
+    .await;
+    let start = |address: &str| {
+        test::TestRequest::post()
+            .uri("/auth/start")
+            .set_json(WalletAddress {
+                address: address.into(),
+            })
+            .to_request()
+    };
+    assert!(app_state.origin_allowed("http://localhost:8000"));
+
+    let mut reloaded = config.clone();
+    reloaded.client_origin_url = vec!["https://example.com".parse().unwrap()];
+    reloaded.challenge_template = Some("Sign in to Example".into());
+    reloaded.rate_limit_address = 1;
+    app_state.reload(&reloaded);
+
+    assert!(!app_state.origin_allowed("http://localhost:8000"));
+    assert!(app_state.origin_allowed("https://example.com"));
+    let challenge: Challenge = test::call_and_read_body_json(&app, start("0x01")).await;
+    assert!(challenge.challenge.contains("Sign in to Example"));
+    let response = test::call_service(&app, start("0x01")).await;
+    assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+}
+
+fn vault_response(client_secret: &str, admin_token: &str, db_password: &str) -> (u16, String) {
+    let data = serde_json::json!({
+        "data": {
+            "data": {
+                "client_secret": client_secret,
+                "admin_token": admin_token,
+                "db_password": db_password,
+            },
+            "metadata": { "version": 1 },
+        }
+    });
+    (200, data.to_string())
+}
+
+#[actix_web::test]
+async fn test_secret_rotation() {
+    let (pool, mut config) = init_test_db().await;
+    let (url, rx) = spawn_http_server(vec![
+        vault_response("vault-secret", "admin", &config.db_password),
+        vault_response("rotated-secret", "rotated-admin", &config.db_password),
+        (500, String::new()),
+    ]);
+    config.vault_addr = Some(Url::parse(&url).unwrap());
+    config.vault_token = Some("token".into());
+    config.vault_path = "avanguard-test".into();
+    let provider = VaultProvider::new(&config).unwrap().unwrap();
+    load_secrets(&mut config, Some(&provider)).await.unwrap();
+    assert_eq!(config.client_secret, "vault-secret");
+    let (head, _) = rx.recv_timeout(StdDuration::from_secs(10)).unwrap();
+    assert!(head.starts_with("get /v1/secret/data/avanguard-test"));
+    assert_eq!(header_value(&head, "x-vault-token"), Some("token"));
+
+    let app_state = web::Data::new(AppState::new(config.clone(), pool.clone()).unwrap());
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state.clone())
+            .configure(config_service),
+    )
+    .await;
+    let list_webhooks = |token: &str| {
+        test::TestRequest::get()
+            .uri("/api/webhook")
+            .insert_header((http::header::AUTHORIZATION, format!("Bearer {token}")))
+            .to_request()
+    };
+    let response = test::call_service(&app, list_webhooks("admin")).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+
+    // Rotated secrets take effect
+    assert!(refresh_secrets(&app_state, Some(&provider)).await.unwrap());
+    assert_eq!(app_state.secrets().client_secret, "rotated-secret");
+    let response = test::call_service(&app, list_webhooks("admin")).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, list_webhooks("rotated-admin")).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+
+    // Failed refresh keeps current secrets
+    assert!(refresh_secrets(&app_state, Some(&provider)).await.is_err());
+    assert_eq!(app_state.secrets().client_secret, "rotated-secret");
+
+    // Refresh tokens hashed with the previous refresh token secret stay valid
+    Wallet::new("0x01".into()).save(&pool).await.unwrap();
+    let wallet_id: i64 = query_scalar("SELECT id FROM wallet WHERE address = '0x01'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let mut secrets = app_state.secrets();
+    let mut tokens = Vec::new();
+    for _ in 0..2 {
+        let (mut refresh_token, token) = RefreshToken::new(
+            wallet_id,
+            None,
+            3600,
+            secrets.refresh_token_secret.as_bytes(),
+        );
+        refresh_token.save(&pool).await.unwrap();
+        tokens.push(token);
+    }
+    secrets.refresh_token_secret = "rotated refresh token secret".into();
+    assert!(app_state.set_secrets(secrets.clone()));
+    let refresh = |refresh_token: &str| {
+        test::TestRequest::post()
+            .uri("/api/v1/refresh")
+            .set_json(RefreshTokenRequest {
+                refresh_token: refresh_token.into(),
+            })
+            .to_request()
+    };
+    let token: JwtToken = test::call_and_read_body_json(&app, refresh(&tokens[0])).await;
+    let response = test::call_service(&app, refresh(&token.refresh_token)).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+
+    // Only the last replaced secret is kept
+    secrets.refresh_token_secret = "refresh token secret rotated twice".into();
+    assert!(app_state.set_secrets(secrets));
+    let response = test::call_service(&app, refresh(&tokens[1])).await;
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+}
+
+/// Requires Vault dev server, e.g. `docker run -p 8200:8200 -e VAULT_DEV_ROOT_TOKEN_ID=root vault`
+/// and `AG_TEST_VAULT_ADDR=http://localhost:8200 AG_TEST_VAULT_TOKEN=root`.
+#[actix_web::test]
+#[ignore]
+async fn test_vault_provider() {
+    let mut config = Config::parse_from(["avanguard"]);
+    config.vault_addr = Some(Url::parse(&std::env::var("AG_TEST_VAULT_ADDR").unwrap()).unwrap());
+    config.vault_token = Some(std::env::var("AG_TEST_VAULT_TOKEN").unwrap());
+    config.vault_path = format!("avanguard-test-{}", Uuid::new_v4());
+    let secret = "s".repeat(64);
+    let response = reqwest::Client::new()
+        .post(
+            config
+                .vault_addr
+                .as_ref()
+                .unwrap()
+                .join(&format!("v1/secret/data/{}", config.vault_path))
+                .unwrap(),
+        )
+        .header("x-vault-token", config.vault_token.as_ref().unwrap())
+        .body(serde_json::json!({ "data": { "client_secret": secret } }).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let provider = VaultProvider::new(&config).unwrap().unwrap();
+    let secrets = provider.fetch().await.unwrap();
+    assert_eq!(secrets["client_secret"], secret);
+}
+
+/// Test certificates in `tests/certs`, signed by test CA in `ca.pem`.
+fn cert_path(name: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/certs")
+        .join(name)
+}
+
+#[actix_web::test]
+async fn test_tls_listener() {
+    let (pool, mut config) = init_test_db().await;
+    let dir = std::env::temp_dir().join(format!("avanguard-tls-{}", Uuid::new_v4()));
+    std::fs::create_dir(&dir).unwrap();
+    for name in ["server.pem", "server.key"] {
+        std::fs::copy(cert_path(name), dir.join(name)).unwrap();
+    }
+    config.tls_cert = Some(dir.join("server.pem"));
+    config.tls_key = Some(dir.join("server.key"));
+    config.admin_client_ca = Some(cert_path("ca.pem"));
+    config.admin_token = Some("admin".into());
+    let (tls_config, resolver) = tls_config(&config).unwrap().unwrap();
+
+    let app_state = web::Data::new(AppState::new(config, pool).unwrap());
+    let tcp_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = tcp_listener.local_addr().unwrap().port();
+    let server = actix_web::HttpServer::new(move || {
+        App::new()
+            .app_data(app_state.clone())
+            .configure(config_service)
+    })
+    .on_connect(on_connect)
+    .workers(1)
+    .listen_rustls_0_21(tcp_listener, tls_config)
+    .unwrap()
+    .run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let ca = reqwest::Certificate::from_pem(&std::fs::read(cert_path("ca.pem")).unwrap()).unwrap();
+    let identity = reqwest::Identity::from_pkcs8_pem(
+        &std::fs::read(cert_path("client.pem")).unwrap(),
+        &std::fs::read(cert_path("client.key")).unwrap(),
+    )
+    .unwrap();
+    let client = reqwest::Client::builder()
+        .add_root_certificate(ca.clone())
+        .build()
+        .unwrap();
+    let mtls_client = reqwest::Client::builder()
+        .add_root_certificate(ca)
+        .identity(identity)
+        .build()
+        .unwrap();
+    let url = |path: &str| format!("https://localhost:{port}{path}");
+
+    // Client certificate is only required by admin API
+    let response = client.get(url("/api/health")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let response = client
+        .get(url("/api/webhook"))
+        .bearer_auth("admin")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let response = mtls_client
+        .get(url("/api/webhook"))
+        .bearer_auth("admin")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let response = mtls_client.get(url("/api/webhook")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    // Invalid certificate is not loaded, the current one is still served
+    std::fs::write(dir.join("server.pem"), "invalid").unwrap();
+    assert!(resolver.reload().is_err());
+    std::fs::copy(cert_path("server.pem"), dir.join("server.pem")).unwrap();
+    assert!(resolver.reload().is_ok());
+    let response = client.get(url("/api/health")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    handle.stop(true).await;
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_health_probes() {
+    let (pool, config) = init_test_db().await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new(config, pool.clone()).unwrap()))
+            .configure(config_service),
+    )
+    .await;
+
+    let request = test::TestRequest::get().uri("/health/live").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+
+    let request = test::TestRequest::get().uri("/health/ready").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    let readiness: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(readiness["ready"], true);
+    assert_eq!(readiness["checks"]["database"], "ok");
+    assert_eq!(readiness["checks"]["migrations"], "ok");
+    assert_eq!(readiness["checks"]["signing_key"], "ok");
+
+    // Latest migration not applied
+    query(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT max(version) FROM _sqlx_migrations)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let request = test::TestRequest::get().uri("/health/ready").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    let readiness: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(readiness["ready"], false);
+    assert_eq!(readiness["checks"]["migrations"], "1 pending migrations");
+
+    // Database unavailable
+    pool.close().await;
+    let request = test::TestRequest::get().uri("/health/ready").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    let readiness: serde_json::Value = test::read_body_json(response).await;
+    assert_ne!(readiness["checks"]["database"], "ok");
+    assert_eq!(readiness["checks"]["signing_key"], "ok");
+
+    let (_, address) = generate_wallet();
+    let request = test::TestRequest::post()
+        .uri("/auth/start")
+        .set_json(WalletAddress { address })
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        response.headers().get(http::header::RETRY_AFTER).unwrap(),
+        "5"
+    );
+    let error: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(error["error"], "DatabaseUnavailable");
+
+    // Refresh tokens aren't reported as invalid, so clients keep their sessions
+    let request = test::TestRequest::post()
+        .uri("/api/v1/refresh")
+        .set_json(RefreshTokenRequest {
+            refresh_token: "token".into(),
+        })
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        response.headers().get(http::header::RETRY_AFTER).unwrap(),
+        "5"
+    );
+    let error: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(error["error"], "temporarily_unavailable");
+}
+
+#[actix_web::test]
+async fn test_db_connect_retry() {
+    let config = Config::parse();
+    // Nothing listens on the port once the listener is dropped
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let start = std::time::Instant::now();
+    let err = init_db(
+        "127.0.0.1",
+        port,
+        &config.db_name,
+        &config.db_user,
+        &config.db_password,
+        1,
+    )
+    .await
+    .unwrap_err();
+    assert!(is_unavailable(&err));
+    // Single retry after one second
+    assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+}
+
+#[actix_web::test]
+async fn test_cors() {
+    let (pool, mut config) = init_test_db().await;
+    config.client_origin_url = vec![
+        "https://app.example.com".parse().unwrap(),
+        "https://*.preview.example.com".parse().unwrap(),
+    ];
+    let app_state = web::Data::new(AppState::new(config.clone(), pool.clone()).unwrap());
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state.clone())
+            .wrap(cors(app_state.clone()))
+            .configure(config_service),
+    )
+    .await;
+    let preflight = |origin: &str| {
+        test::TestRequest::default()
+            .method(http::Method::OPTIONS)
+            .uri("/auth/start")
+            .insert_header((http::header::ORIGIN, origin))
+            .insert_header((http::header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .insert_header((http::header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type"))
+            .to_request()
+    };
+    fn allowed_origin<B>(response: &actix_web::dev::ServiceResponse<B>) -> Option<String> {
+        response
+            .headers()
+            .get(http::header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    for origin in [
+        "https://app.example.com",
+        "https://pr-42.preview.example.com",
+    ] {
+        let response = test::call_service(&app, preflight(origin)).await;
+        assert_eq!(response.status(), http::StatusCode::OK, "{origin}");
+        assert_eq!(allowed_origin(&response).as_deref(), Some(origin));
+        assert!(response
+            .headers()
+            .get(http::header::ACCESS_CONTROL_ALLOW_METHODS)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("POST"));
+    }
+    for origin in ["https://evil.example.com", "https://preview.example.com"] {
+        let response = test::call_service(&app, preflight(origin)).await;
+        assert_eq!(allowed_origin(&response), None, "{origin}");
+    }
+
+    // Actual request gets CORS headers for allowed origin only
+    let request = test::TestRequest::get()
+        .uri("/health/live")
+        .insert_header((http::header::ORIGIN, "https://app.example.com"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(
+        allowed_origin(&response).as_deref(),
+        Some("https://app.example.com")
+    );
+
+    // Origins of registered clients are allowed once loaded
+    let mut client = Client::new("mobile".into(), "Mobile".into());
+    client.allowed_origins = vec!["https://mobile.example.com".into()];
+    client.save(&pool).await.unwrap();
+    let response = test::call_service(&app, preflight("https://mobile.example.com")).await;
+    assert_eq!(allowed_origin(&response), None);
+    app_state.load_client_origins().await.unwrap();
+    let response = test::call_service(&app, preflight("https://mobile.example.com")).await;
+    assert_eq!(
+        allowed_origin(&response).as_deref(),
+        Some("https://mobile.example.com")
+    );
+
+    // Origins are reloaded without restart
+    let mut reloaded = config.clone();
+    reloaded.client_origin_url = vec!["https://*.example.com".parse().unwrap()];
+    app_state.reload(&reloaded);
+    let response = test::call_service(&app, preflight("https://evil.example.com")).await;
+    assert_eq!(
+        allowed_origin(&response).as_deref(),
+        Some("https://evil.example.com")
+    );
+}
+
+#[actix_web::test]
+async fn test_db_commands() {
+    let config = Config::parse();
+    let (pool, _) = init_test_db().await;
+    let db_name = Uuid::new_v4().to_string();
+    query(&format!("CREATE DATABASE \"{db_name}\""))
+        .execute(&pool)
+        .await
+        .unwrap();
+    let pool = connect_db(
+        &config.db_host,
+        config.db_port,
+        &db_name,
+        &config.db_user,
+        &config.db_password,
+        0,
+    )
+    .await
+    .unwrap();
+    let run = |command: DbCommand| {
+        let pool = pool.clone();
+        let config = config.clone();
+        async move {
+            let mut out = Vec::new();
+            run_command(&Command::Db(command), &config, &pool, &mut out)
+                .await
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        }
+    };
+    let total = migration_status(&pool).await.unwrap().len();
+
+    // Empty database
+    assert_eq!(pending_migrations(&pool).await.unwrap(), total);
+    let status = run(DbCommand::Status).await;
+    assert_eq!(status.matches("pending").count(), total);
+
+    let output = run(DbCommand::Migrate).await;
+    assert_eq!(output.matches("Applied").count(), total);
+    assert_eq!(pending_migrations(&pool).await.unwrap(), 0);
+    assert!(run(DbCommand::Migrate).await.contains("up to date"));
+
+    let output = run(DbCommand::Rollback { steps: 2 }).await;
+    assert_eq!(output.matches("Reverted").count(), 2);
+    let status = migration_status(&pool).await.unwrap();
+    assert!(!status[total - 1].applied);
+    assert!(!status[total - 2].applied);
+    assert!(status[total - 3].applied);
+
+    // All down migrations revert cleanly and schema can be created again
+    run(DbCommand::Rollback { steps: total }).await;
+    assert_eq!(pending_migrations(&pool).await.unwrap(), total);
+    let tables: i64 = query_scalar(
+        "SELECT count(*) FROM information_schema.tables \
+        WHERE table_schema = 'public' AND table_name != '_sqlx_migrations'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(tables, 0);
+    assert!(run(DbCommand::Rollback { steps: 1 })
+        .await
+        .contains("No migrations"));
+    run(DbCommand::Migrate).await;
+    assert_eq!(pending_migrations(&pool).await.unwrap(), 0);
+}
+
+#[actix_web::test]
+async fn test_admin_commands() {
+    let (pool, mut config) = init_test_db().await;
+    let audit_path = std::env::temp_dir().join(format!("avanguard-audit-{}.log", Uuid::new_v4()));
+    config.audit_log = Some(AuditTarget::File(audit_path.clone()));
+    config.output = OutputFormat::Json;
+    config.event_sink = Some(Url::parse("nats://localhost").unwrap());
+    let run = |command: Command, config: Config| {
+        let pool = pool.clone();
+        async move {
+            let mut out = Vec::new();
+            run_command(&command, &config, &pool, &mut out)
+                .await
+                .map(|()| serde_json::from_slice::<serde_json::Value>(&out).unwrap())
+        }
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .configure(config_service),
+    )
+    .await;
+
+    // Register client, its secret is shown only once
+    let client = run(
+        Command::Client(ClientCommand::Add {
+            client_id: "mobile".into(),
+            name: Some("Mobile app".into()),
+            origins: vec!["https://mobile.example.com/".into()],
+        }),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    let secret = client[0]["secret"].as_str().unwrap().to_string();
+    assert_eq!(client[0]["name"], "Mobile app");
+    assert_eq!(
+        client[0]["allowed_origins"],
+        serde_json::json!(["https://mobile.example.com"])
+    );
+    let clients = run(Command::Client(ClientCommand::List), config.clone())
+        .await
+        .unwrap();
+    assert_eq!(clients.as_array().unwrap().len(), 1);
+    assert!(clients[0].get("secret").is_none());
+    for client_id in ["mobile", config.client_id.as_str()] {
+        let result = run(
+            Command::Client(ClientCommand::Add {
+                client_id: client_id.into(),
+                name: None,
+                origins: Vec::new(),
+            }),
+            config.clone(),
+        )
+        .await;
+        assert!(matches!(result, Err(CliError::Invalid(_))));
+    }
+    let set_origins = |origins: &[&str]| {
+        Command::Client(ClientCommand::SetOrigins {
+            client_id: "mobile".into(),
+            origins: origins.iter().map(|origin| origin.to_string()).collect(),
+        })
+    };
+    for origins in [&["*"][..], &["mobile.example.com"]] {
+        let result = run(set_origins(origins), config.clone()).await;
+        assert!(matches!(result, Err(CliError::Invalid(_))));
+    }
+    let client = run(
+        set_origins(&["https://mobile.example.com", "https://*.mobile.example.com"]),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(client["allowed_origins"].as_array().unwrap().len(), 2);
+
+    // Log in with registered client
+    let (secret_key, address) = generate_wallet();
+    let request = test::TestRequest::post()
+        .uri("/auth/start")
+        .set_json(WalletAddress {
+            address: address.clone(),
+        })
+        .to_request();
+    let challenge: Challenge = test::call_and_read_body_json(&app, request).await;
+    let signature = sign_challenge(&secret_key, &challenge.challenge);
+    let login = |client_id: &str| {
+        test::TestRequest::post()
+            .uri("/auth")
+            .set_json(WalletSignature {
+                address: address.clone(),
+                signature: signature.clone(),
+                nonce: "test".into(),
+                client_id: Some(client_id.into()),
+                scope: String::new(),
+            })
+            .to_request()
+    };
+    let response = test::call_service(&app, login("unknown")).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let token: JwtToken = test::call_and_read_body_json(&app, login("mobile")).await;
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_audience(&["mobile"]);
+    decode::<Claims>(
+        &token.token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation,
+    )
+    .unwrap();
+    let request = test::TestRequest::get()
+        .uri("/api/session")
+        .insert_header((
+            http::header::AUTHORIZATION,
+            format!("Bearer {}", token.token),
+        ))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(sessions[0]["client_id"], "mobile");
+
+    // Rotated secret invalidates tokens signed with the old one
+    let rotated = run(
+        Command::Client(ClientCommand::RotateSecret {
+            client_id: "mobile".into(),
+        }),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    assert_ne!(rotated[0]["secret"], secret.as_str());
+    let request = test::TestRequest::get()
+        .uri("/api/session")
+        .insert_header((
+            http::header::AUTHORIZATION,
+            format!("Bearer {}", token.token),
+        ))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+    // Wallet commands
+    let wallets = run(Command::Wallet(WalletCommand::List), config.clone())
+        .await
+        .unwrap();
+    assert_eq!(wallets[0]["address"], address.as_str());
+    let wallet = run(
+        Command::Wallet(WalletCommand::Show {
+            address: address.to_uppercase().replace("0X", "0x"),
+        }),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(wallet["sessions"].as_array().unwrap().len(), 1);
+    let result = run(
+        Command::Wallet(WalletCommand::Show {
+            address: "0x01".into(),
+        }),
+        config.clone(),
+    )
+    .await;
+    assert!(matches!(result, Err(CliError::NotFound(_))));
+
+    run(
+        Command::Wallet(WalletCommand::Disable {
+            address: address.clone(),
+        }),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    let request = test::TestRequest::post()
+        .uri("/refresh")
+        .set_json(RefreshTokenRequest {
+            refresh_token: token.refresh_token.clone(),
+        })
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    let response = test::call_service(&app, login("mobile")).await;
+    assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+    run(
+        Command::Wallet(WalletCommand::Enable {
+            address: address.clone(),
+        }),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    let _: JwtToken = test::call_and_read_body_json(&app, login("mobile")).await;
+
+    let revoked = run(
+        Command::Token(TokenCommand::Revoke {
+            wallet: address.clone(),
+        }),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(revoked["revoked"], 1);
+
+    run(
+        Command::Wallet(WalletCommand::Delete {
+            address: address.clone(),
+        }),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    let wallets = run(Command::Wallet(WalletCommand::List), config.clone())
+        .await
+        .unwrap();
+    assert!(wallets.as_array().unwrap().is_empty());
+    let deleted: i64 = query_scalar(
+        "SELECT count(*) FROM event_outbox WHERE event = 'wallet.deleted' AND key = $1",
+    )
+    .bind(&address)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(deleted, 1);
+
+    // Keys are written to configured secret files
+    let key = run(Command::Keys(KeysCommand::Generate), config.clone())
+        .await
+        .unwrap();
+    assert_eq!(key["key"].as_str().unwrap().len(), 64);
+    let result = run(
+        Command::Keys(KeysCommand::Rotate {
+            secret: SecretName::AdminToken,
+        }),
+        config.clone(),
+    )
+    .await;
+    assert!(matches!(result, Err(CliError::Invalid(_))));
+    let token_path = std::env::temp_dir().join(format!("avanguard-token-{}", Uuid::new_v4()));
+    std::fs::write(&token_path, "old").unwrap();
+    config.admin_token_file = Some(token_path.clone());
+    run(
+        Command::Keys(KeysCommand::Rotate {
+            secret: SecretName::AdminToken,
+        }),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    config.read_secret_files().unwrap();
+    std::fs::remove_file(&token_path).unwrap();
+    assert_eq!(config.admin_token.unwrap().len(), 64);
+
+    let actions: Vec<String> = std::fs::read_to_string(&audit_path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|event| event["event"] == "admin_action")
+        .map(|event| event["action"].as_str().unwrap().to_string())
+        .collect();
+    std::fs::remove_file(&audit_path).unwrap();
+    assert_eq!(
+        actions,
+        [
+            "add_client",
+            "set_client_origins",
+            "rotate_client_secret",
+            "disable_wallet",
+            "enable_wallet",
+            "delete_wallet",
+            "rotate_admin_token"
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn test_openapi() {
+    let (pool, config) = init_test_db().await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new(config, pool).unwrap()))
+            .configure(config_service),
+    )
+    .await;
+    let request = test::TestRequest::get()
+        .uri("/api/v1/openapi.json")
+        .to_request();
+    let spec: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+    for (path, method) in [
+        ("/api/v1/auth/start", "post"),
+        ("/api/v1/auth", "post"),
+        ("/api/v1/refresh", "post"),
+        ("/api/v1/session", "get"),
+        ("/api/v1/session", "delete"),
+        ("/api/v1/session/{id}", "delete"),
+    ] {
+        assert!(spec["paths"][path][method].is_object(), "{method} {path}");
+    }
+    assert_eq!(
+        spec["paths"]["/api/v1/auth"]["post"]["responses"]["200"]["content"]["application/json"]
+            ["schema"]["$ref"],
+        "#/components/schemas/JwtToken"
+    );
+
+    // Schemas describe fields as they are serialized
+    let schemas = &spec["components"]["schemas"];
+    let properties = |schema: &str| {
+        let mut properties: Vec<_> = schemas[schema]["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        properties.sort();
+        properties
+    };
+    let keys = |value: serde_json::Value| {
+        let mut keys: Vec<_> = value.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    };
+    let token = JwtToken {
+        token: String::new(),
+        refresh_token: String::new(),
+    };
+    assert_eq!(properties("JwtToken"), keys(serde_json::json!(token)));
+    let challenge = Challenge {
+        challenge: String::new(),
+    };
+    assert_eq!(properties("Challenge"), keys(serde_json::json!(challenge)));
+    let signature = WalletSignature {
+        address: String::new(),
+        signature: String::new(),
+        nonce: String::new(),
+        client_id: Some(String::new()),
+        scope: String::from("openid"),
+    };
+    assert_eq!(
+        properties("WalletSignature"),
+        keys(serde_json::json!(signature))
+    );
+    assert_eq!(
+        properties("ErrorInfo"),
+        ["error", "locked_until", "message", "request_id"]
+    );
+}
+
+/// Replaces JSON values with names of their types to compare response structure.
+fn json_shape(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Null => "null".into(),
+        serde_json::Value::Bool(_) => "boolean".into(),
+        serde_json::Value::Number(_) => "number".into(),
+        serde_json::Value::String(_) => "string".into(),
+        serde_json::Value::Array(items) => items.iter().map(json_shape).collect(),
+        serde_json::Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| (key.clone(), json_shape(value)))
+            .collect(),
+    }
+}
+
+#[actix_web::test]
+async fn test_api_v1_contract() {
+    let (pool, mut config) = init_test_db().await;
+    config.admin_token = Some("admin".into());
+    config.lockout_threshold = 0;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .configure(config_service),
+    )
+    .await;
+    let admin = (http::header::AUTHORIZATION, "Bearer admin");
+    let (secret_key, address) = generate_wallet();
+    let call = |request: test::TestRequest| {
+        let app = &app;
+        async move {
+            let response = test::call_service(app, request.to_request()).await;
+            let status = response.status().as_u16();
+            let body = test::read_body(response).await;
+            let body = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+            (status, body)
+        }
+    };
+    let error = serde_json::json!({ "error": "string", "message": "string" });
+
+    // Authentication
+    let (status, challenge) = call(
+        test::TestRequest::post()
+            .uri("/api/v1/auth/start")
+            .set_json(serde_json::json!({ "address": address })),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        json_shape(&challenge),
+        serde_json::json!({ "challenge": "string" })
+    );
+    let signature = sign_challenge(&secret_key, challenge["challenge"].as_str().unwrap());
+    let (other_key, _) = generate_wallet();
+    let other_signature = sign_challenge(&other_key, challenge["challenge"].as_str().unwrap());
+    for (signature, status, code) in [
+        ("0x00", 400, "UnsupportedSignatureFormat"),
+        ("0xzz", 400, "MalformedSignature"),
+        (other_signature.as_str(), 401, "SignerMismatch"),
+    ] {
+        let (actual, body) = call(test::TestRequest::post().uri("/api/v1/auth").set_json(
+            serde_json::json!({
+                "address": address,
+                "signature": signature,
+                "nonce": "nonce",
+            }),
+        ))
+        .await;
+        assert_eq!((actual, json_shape(&body)), (status, error.clone()));
+        assert_eq!(body["error"], code);
+    }
+    // Unversioned alias keeps the error returned before versioning
+    for signature in ["0x00", "0xzz", other_signature.as_str()] {
+        let (status, body) = call(test::TestRequest::post().uri("/auth").set_json(
+            serde_json::json!({
+                "address": address,
+                "signature": signature,
+                "nonce": "nonce",
+            }),
+        ))
+        .await;
+        assert_eq!((status, json_shape(&body)), (401, error.clone()));
+        assert_eq!(body["error"], "SignatureIncorrect");
+    }
+    let (status, body) = call(
+        test::TestRequest::post()
+            .uri("/api/v1/auth")
+            .set_json(serde_json::json!({ "address": address, "signature": signature })),
+    )
+    .await;
+    assert_eq!((status, json_shape(&body)), (400, error.clone()));
+    assert_eq!(body["error"], "InvalidRequest");
+    let (status, body) = call(
+        test::TestRequest::post()
+            .uri("/api/v1/auth")
+            .insert_header((http::header::CONTENT_TYPE, "text/plain"))
+            .set_payload("address"),
+    )
+    .await;
+    assert_eq!((status, json_shape(&body)), (400, error.clone()));
+    let (status, tokens) = call(test::TestRequest::post().uri("/api/v1/auth").set_json(
+        serde_json::json!({
+            "address": address,
+            "signature": signature,
+            "nonce": "nonce",
+        }),
+    ))
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        json_shape(&tokens),
+        serde_json::json!({ "token": "string", "refresh_token": "string" })
+    );
+    let (status, tokens) = call(
+        test::TestRequest::post()
+            .uri("/api/v1/refresh")
+            .set_json(serde_json::json!({ "refresh_token": tokens["refresh_token"] })),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        json_shape(&tokens),
+        serde_json::json!({ "token": "string", "refresh_token": "string" })
+    );
+    let (status, body) = call(
+        test::TestRequest::post()
+            .uri("/api/v1/refresh")
+            .set_json(serde_json::json!({ "refresh_token": "invalid" })),
+    )
+    .await;
+    let oauth_error = serde_json::json!({ "error": "string", "error_description": "string" });
+    assert_eq!((status, json_shape(&body)), (400, oauth_error.clone()));
+    assert_eq!(body["error"], "invalid_grant");
+    let (status, body) = call(
+        test::TestRequest::post()
+            .uri("/api/v1/refresh")
+            .set_json(serde_json::json!({})),
+    )
+    .await;
+    assert_eq!((status, json_shape(&body)), (400, oauth_error));
+    assert_eq!(body["error"], "invalid_request");
+    let (status, body) = call(
+        test::TestRequest::post()
+            .uri("/refresh")
+            .set_json(serde_json::json!({ "refresh_token": "invalid" })),
+    )
+    .await;
+    assert_eq!((status, json_shape(&body)), (401, error.clone()));
+    assert_eq!(body["error"], "TokenNotFound");
+
+    // Sessions of the wallet owner
+    let bearer = (
+        http::header::AUTHORIZATION,
+        format!("Bearer {}", tokens["token"].as_str().unwrap()),
+    );
+    let session = serde_json::json!({
+        "login_event_id": "number",
+        "logged_in_at": "string",
+        "ip": "null",
+        "user_agent": "null",
+        "client_id": "string",
+        "method": "string",
+        "expires_at": "string",
+    });
+    let (status, sessions) = call(
+        test::TestRequest::get()
+            .uri("/api/v1/session")
+            .insert_header(bearer.clone()),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(json_shape(&sessions), serde_json::json!([session]));
+    let (status, body) = call(
+        test::TestRequest::delete()
+            .uri("/api/v1/session/0")
+            .insert_header(bearer.clone()),
+    )
+    .await;
+    assert_eq!((status, json_shape(&body)), (404, error.clone()));
+    let (status, body) = call(
+        test::TestRequest::delete()
+            .uri("/api/v1/session/abc")
+            .insert_header(bearer.clone()),
+    )
+    .await;
+    assert_eq!((status, json_shape(&body)), (400, error.clone()));
+    assert_eq!(body["error"], "InvalidRequest");
+
+    // Admin API
+    let (status, logins) = call(
+        test::TestRequest::get()
+            .uri(&format!("/api/v1/wallet/{address}/logins"))
+            .insert_header(admin.clone()),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        json_shape(&logins[0]),
+        serde_json::json!({
+            "id": "number",
+            "wallet_id": "number",
+            "timestamp": "string",
+            "ip": "null",
+            "user_agent": "null",
+            "client_id": "string",
+            "method": "string",
+            "outcome": "string",
+        })
+    );
+    let (status, sessions) = call(
+        test::TestRequest::get()
+            .uri(&format!("/api/v1/wallet/{address}/sessions"))
+            .insert_header(admin.clone()),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(json_shape(&sessions), serde_json::json!([session]));
+    let (status, _) = call(test::TestRequest::get().uri("/api/v1/wallet")).await;
+    assert_eq!(status, 401);
+    let (status, wallets) = call(
+        test::TestRequest::get()
+            .uri("/api/v1/wallet")
+            .insert_header(admin.clone()),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        json_shape(&wallets),
+        serde_json::json!([{
+            "id": "number",
+            "address": "string",
+            "challenge_message": "string",
+            "challenge_signature": "string",
+            "creation_timestamp": "string",
+            "validation_timestamp": "string",
+            "failed_login_attempts": "number",
+            "locked_until": "null",
+            "disabled_at": "null",
+        }])
+    );
+    let (status, _) = call(
+        test::TestRequest::delete()
+            .uri(&format!("/api/v1/wallet/{address}/lockout"))
+            .insert_header(admin.clone()),
+    )
+    .await;
+    assert_eq!(status, 204);
+    let (status, webhook) = call(
+        test::TestRequest::post()
+            .uri("/api/v1/webhook")
+            .insert_header(admin.clone())
+            .set_json(serde_json::json!({
+                "url": "https://example.com/hook",
+                "events": ["login.succeeded"],
+            })),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        json_shape(&webhook),
+        serde_json::json!({
+            "id": "number",
+            "url": "string",
+            "events": ["string"],
+            "enabled": "boolean",
+            "created_at": "string",
+            "secret": "string",
+        })
+    );
+    let (status, webhooks) = call(
+        test::TestRequest::get()
+            .uri("/api/v1/webhook")
+            .insert_header(admin.clone()),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        json_shape(&webhooks),
+        serde_json::json!([{
+            "id": "number",
+            "url": "string",
+            "events": ["string"],
+            "enabled": "boolean",
+            "created_at": "string",
+        }])
+    );
+    let (status, _) = call(
+        test::TestRequest::delete()
+            .uri(&format!("/api/v1/webhook/{}", webhook["id"]))
+            .insert_header(admin.clone()),
+    )
+    .await;
+    assert_eq!(status, 204);
+    let (status, body) = call(test::TestRequest::get().uri("/api/v1/webhook")).await;
+    assert_eq!((status, json_shape(&body)), (401, error.clone()));
+
+    let (status, _) = call(
+        test::TestRequest::delete()
+            .uri("/api/v1/session")
+            .insert_header(bearer),
+    )
+    .await;
+    assert_eq!(status, 204);
+    let request = test::TestRequest::get().uri("/api/v1/health").to_request();
+    assert_eq!(test::call_and_read_body(&app, request).await, "alive");
+}
+
+#[actix_web::test]
+async fn test_api_deprecated_paths() {
+    let (pool, mut config) = init_test_db().await;
+    config.legacy_api_sunset = "2027-01-31".parse().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .configure(config_service),
+    )
+    .await;
+    let header = |response: &actix_web::dev::ServiceResponse, name: &str| {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_string())
+    };
+
+    // Unversioned paths serve the same endpoints, marked as deprecated
+    for (path, successor) in [
+        ("/auth/start", "/api/v1/auth/start"),
+        ("/api/health", "/api/v1/health"),
+    ] {
+        let request = if path == "/auth/start" {
+            test::TestRequest::post().set_json(WalletAddress {
+                address: "0x01".into(),
+            })
+        } else {
+            test::TestRequest::get()
+        };
+        let response = test::call_service(&app, request.uri(path).to_request()).await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(
+            header(&response, "deprecation").as_deref(),
+            Some("@1792368000")
+        );
+        assert_eq!(
+            header(&response, "sunset").as_deref(),
+            Some("Sun, 31 Jan 2027 00:00:00 GMT")
+        );
+        assert_eq!(
+            header(&response, "link"),
+            Some(format!("<{successor}>; rel=\"successor-version\""))
+        );
+    }
+    let request = test::TestRequest::get().uri("/api/session").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    assert!(header(&response, "deprecation").is_some());
+
+    // Versioned, operational and unknown paths aren't deprecated
+    for path in ["/api/v1/health", "/health/live", "/metrics", "/api/unknown"] {
+        let request = test::TestRequest::get().uri(path).to_request();
+        let response = test::call_service(&app, request).await;
+        assert!(header(&response, "deprecation").is_none(), "{path}");
+    }
+
+    // Sunset dates which HTTP dates can't represent are left out
+    for (year, month, day) in [(1969, 12, 31), (10_000, 1, 1)] {
+        config.legacy_api_sunset = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(
+                    AppState::new(config.clone(), pool.clone()).unwrap(),
+                ))
+                .configure(config_service),
+        )
+        .await;
+        let request = test::TestRequest::get().uri("/api/health").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert!(header(&response, "deprecation").is_some());
+        assert!(header(&response, "sunset").is_none(), "{year}");
+    }
+}
+
+#[actix_web::test]
+async fn test_embedded_builder() {
+    let (pool, _) = init_test_db().await;
+    assert!(Avanguard::builder().build().is_err());
+    // Unusable audit log is reported instead of panicking
+    let config = Config {
+        audit_log: Some(AuditTarget::File("/nonexistent/audit.log".into())),
+        ..Config::default()
+    };
+    assert!(matches!(
+        Avanguard::builder()
+            .config(config)
+            .store(pool.clone())
+            .build(),
+        Err(ConfigError::AuditLog(_))
+    ));
+
+    let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let hook_events = events.clone();
+    let avanguard = Avanguard::builder()
+        .issuer(Url::parse("https://auth.example.com").unwrap())
+        .client("app", "app secret")
+        .add_client("mobile", "mobile secret")
+        .refresh_token_secret("refresh secret")
+        .challenge_template("Sign in to Example")
+        .hook(move |event: &AuthEvent| hook_events.lock().unwrap().push(event.name()))
+        .store(pool.clone())
+        .build()
+        .unwrap();
+    let app = test::init_service(App::new().service(avanguard.scope("/identity"))).await;
+
+    let (secret_key, address) = generate_wallet();
+    let request = test::TestRequest::post()
+        .uri("/identity/api/v1/auth/start")
+        .set_json(WalletAddress {
+            address: address.clone(),
+        })
+        .to_request();
+    let challenge: Challenge = test::call_and_read_body_json(&app, request).await;
+    assert!(challenge.challenge.contains("Sign in to Example"));
+    let request = test::TestRequest::post()
+        .uri("/identity/api/v1/auth")
+        .set_json(WalletSignature {
+            address: address.clone(),
+            signature: sign_challenge(&secret_key, &challenge.challenge),
+            nonce: "nonce".into(),
+            client_id: Some("mobile".into()),
+            scope: String::new(),
+        })
+        .to_request();
+    let token: JwtToken = test::call_and_read_body_json(&app, request).await;
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_audience(&["mobile"]);
+    validation.set_issuer(&["https://auth.example.com/"]);
+    let claims = decode::<Claims>(
+        &token.token,
+        &DecodingKey::from_secret(b"mobile secret"),
+        &validation,
+    )
+    .unwrap()
+    .claims;
+    assert_eq!(claims.sub, address);
+    assert_eq!(
+        *events.lock().unwrap(),
+        ["wallet.registered", "login.succeeded"]
+    );
+
+    // Paths used before versioning are only served on request
+    let request = test::TestRequest::post()
+        .uri("/identity/auth/start")
+        .set_json(WalletAddress { address })
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+
+    // So are metrics and probes
+    for path in ["/identity/metrics", "/identity/health/live"] {
+        let request = test::TestRequest::get().uri(path).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), http::StatusCode::NOT_FOUND, "{path}");
+    }
+    let avanguard = Avanguard::builder()
+        .client("app", "app secret")
+        .refresh_token_secret("refresh secret")
+        .metrics(true)
+        .health_probes(true)
+        .store(pool)
+        .build()
+        .unwrap();
+    let app = test::init_service(App::new().service(avanguard.scope("/identity"))).await;
+    for path in [
+        "/identity/metrics",
+        "/identity/health/live",
+        "/identity/health/ready",
+    ] {
+        let request = test::TestRequest::get().uri(path).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), http::StatusCode::OK, "{path}");
+    }
+}
+
+/// Claims provider adding client and scopes the token is requested with.
+struct RequestClaims;
+
+#[async_trait]
+impl ClaimsProvider for RequestClaims {
+    async fn claims(
+        &self,
+        request: &ClaimsRequest<'_>,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, ClaimsError> {
+        let serde_json::Value::Object(claims) = serde_json::json!({
+            "tenant": request.client_id,
+            "scopes": request.scopes,
+            "role": "user",
+        }) else {
+            unreachable!()
+        };
+        Ok(claims)
+    }
+}
+
+#[actix_web::test]
+async fn test_claims_provider() {
+    let (pool, mut config) = init_test_db().await;
+    config.static_claims = true;
+    let (secret_key, address) = generate_wallet();
+
+    // Static claims are managed with CLI
+    let mut out = Vec::new();
+    let command = Command::Claims(ClaimsCommand::Set {
+        address: address.to_uppercase().replace("0X", "0x"),
+        claims: r#"{"role": "admin", "features": ["beta"], "sub": "0x0"}"#.into(),
+    });
+    run_command(&command, &config, &pool, &mut out)
+        .await
+        .unwrap();
+    let command = Command::Claims(ClaimsCommand::Set {
+        address: address.clone(),
+        claims: "[]".into(),
+    });
+    assert!(matches!(
+        run_command(&command, &config, &pool, &mut out).await,
+        Err(CliError::Invalid(_))
+    ));
+    let claims = StaticClaims::get(&pool, &address).await.unwrap().unwrap();
+    assert_eq!(claims["role"], "admin");
+
+    let avanguard = Avanguard::builder()
+        .config(config.clone())
+        .store(pool.clone())
+        .claims_provider(RequestClaims)
+        .build()
+        .unwrap();
+    let app = test::init_service(App::new().service(avanguard.scope(""))).await;
+    let request = test::TestRequest::post()
+        .uri("/api/v1/auth/start")
+        .set_json(WalletAddress {
+            address: address.clone(),
+        })
+        .to_request();
+    let challenge: Challenge = test::call_and_read_body_json(&app, request).await;
+    let request = test::TestRequest::post()
+        .uri("/api/v1/auth")
+        .set_json(WalletSignature {
+            address: address.clone(),
+            signature: sign_challenge(&secret_key, &challenge.challenge),
+            nonce: "nonce".into(),
+            client_id: None,
+            scope: "openid  profile".into(),
+        })
+        .to_request();
+    let token: JwtToken = test::call_and_read_body_json(&app, request).await;
+    let decode_claims = |token: &str| {
+        decode::<serde_json::Value>(
+            token,
+            &DecodingKey::from_secret(config.client_secret.as_ref()),
+            &Validation::new(Algorithm::HS256),
+        )
+        .unwrap()
+        .claims
+    };
+    let claims = decode_claims(&token.token);
+    // Later providers override earlier ones, reserved claims are kept
+    assert_eq!(claims["sub"], address);
+    assert_eq!(claims["role"], "user");
+    assert_eq!(claims["features"], serde_json::json!(["beta"]));
+    assert_eq!(claims["tenant"], config.client_id);
+    assert_eq!(claims["scopes"], serde_json::json!(["openid", "profile"]));
+
+    // Tokens with custom claims authenticate wallet owners
+    let request = test::TestRequest::get()
+        .uri("/api/v1/session")
+        .insert_header((
+            http::header::AUTHORIZATION,
+            format!("Bearer {}", token.token),
+        ))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+
+    // Refreshed tokens get claims for scopes requested at login
+    let request = test::TestRequest::post()
+        .uri("/api/v1/refresh")
+        .set_json(RefreshTokenRequest {
+            refresh_token: token.refresh_token,
+        })
+        .to_request();
+    let token: JwtToken = test::call_and_read_body_json(&app, request).await;
+    let claims = decode_claims(&token.token);
+    assert_eq!(claims["features"], serde_json::json!(["beta"]));
+    assert_eq!(claims["scopes"], serde_json::json!(["openid", "profile"]));
+
+    let command = Command::Claims(ClaimsCommand::Unset {
+        address: address.clone(),
+    });
+    run_command(&command, &config, &pool, &mut out)
+        .await
+        .unwrap();
+    assert!(matches!(
+        run_command(&command, &config, &pool, &mut out).await,
+        Err(CliError::NotFound(_))
+    ));
+}
+
+#[actix_web::test]
+async fn test_groups() {
+    let (pool, mut config) = init_test_db().await;
+    config.admin_token = Some("admin".into());
+    config.groups_claim = "roles".into();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                AppState::new(config.clone(), pool.clone()).unwrap(),
+            ))
+            .configure(config_service),
+    )
+    .await;
+    let admin = (http::header::AUTHORIZATION, "Bearer admin");
+    let (secret_key, address) = generate_wallet();
+    let login = |scope: &str| {
+        let app = &app;
+        let secret_key = &secret_key;
+        let address = address.clone();
+        let scope = scope.to_string();
+        async move {
+            let request = test::TestRequest::post()
+                .uri("/api/v1/auth/start")
+                .set_json(WalletAddress {
+                    address: address.clone(),
+                })
+                .to_request();
+            let challenge: Challenge = test::call_and_read_body_json(app, request).await;
+            let request = test::TestRequest::post()
+                .uri("/api/v1/auth")
+                .set_json(WalletSignature {
+                    address,
+                    signature: sign_challenge(secret_key, &challenge.challenge),
+                    nonce: "nonce".into(),
+                    client_id: None,
+                    scope,
+                })
+                .to_request();
+            let token: JwtToken = test::call_and_read_body_json(app, request).await;
+            token
+        }
+    };
+    let decode_claims = |token: &JwtToken| {
+        decode::<serde_json::Value>(
+            &token.token,
+            &DecodingKey::from_secret(config.client_secret.as_ref()),
+            &Validation::new(Algorithm::HS256),
+        )
+        .unwrap()
+        .claims
+    };
+    // Registers the wallet
+    let claims = decode_claims(&login("openid groups").await);
+    assert_eq!(claims["roles"], serde_json::json!([]));
+
+    // Groups are managed with admin API
+    for name in ["grafana-admin", "defguard-user"] {
+        let request = test::TestRequest::post()
+            .uri("/api/v1/group")
+            .insert_header(admin.clone())
+            .set_json(serde_json::json!({ "name": name }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+    }
+    let request = test::TestRequest::post()
+        .uri("/api/v1/group")
+        .insert_header(admin.clone())
+        .set_json(serde_json::json!({ "name": "grafana-admin" }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::CONFLICT);
+    let error: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(error["error"], "GroupExists");
+    let request = test::TestRequest::post()
+        .uri("/api/v1/group")
+        .insert_header(admin.clone())
+        .set_json(serde_json::json!({ "name": "two words" }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    let request = test::TestRequest::post()
+        .uri("/api/v1/group/grafana-admin/wallets")
+        .insert_header(admin.clone())
+        .set_json(serde_json::json!({ "address": address.to_uppercase().replace("0X", "0x") }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    let request = test::TestRequest::post()
+        .uri("/api/v1/group/missing/wallets")
+        .insert_header(admin.clone())
+        .set_json(serde_json::json!({ "address": address }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    let request = test::TestRequest::get()
+        .uri("/api/v1/group/grafana-admin/wallets")
+        .insert_header(admin.clone())
+        .to_request();
+    let members: Vec<String> = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(members, [address.as_str()]);
+
+    // And with CLI
+    let mut out = Vec::new();
+    let command = Command::Group(GroupCommand::Add {
+        name: "grafana-admin".into(),
+    });
+    assert!(matches!(
+        run_command(&command, &config, &pool, &mut out).await,
+        Err(CliError::Invalid(_))
+    ));
+    let command = Command::Group(GroupCommand::Assign {
+        name: "defguard-user".into(),
+        address: address.clone(),
+    });
+    run_command(&command, &config, &pool, &mut out)
+        .await
+        .unwrap();
+    let request = test::TestRequest::get()
+        .uri(&format!("/api/v1/wallet/{address}/groups"))
+        .insert_header(admin.clone())
+        .to_request();
+    let groups: Vec<String> = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(groups, ["defguard-user", "grafana-admin"]);
+
+    // Groups are only emitted with `groups` scope
+    let claims = decode_claims(&login("openid groups").await);
+    assert_eq!(
+        claims["roles"],
+        serde_json::json!(["defguard-user", "grafana-admin"])
+    );
+    assert!(claims.get("groups").is_none());
+    let claims = decode_claims(&login("openid").await);
+    assert!(claims.get("roles").is_none());
+
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/v1/group/grafana-admin/wallets/{address}"))
+        .insert_header(admin.clone())
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/v1/group/grafana-admin/wallets/{address}"))
+        .insert_header(admin.clone())
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    let error: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(error["error"], "NotGroupMember");
+    let command = Command::Group(GroupCommand::Delete {
+        name: "defguard-user".into(),
+    });
+    run_command(&command, &config, &pool, &mut out)
+        .await
+        .unwrap();
+    let claims = decode_claims(&login("groups").await);
+    assert_eq!(claims["roles"], serde_json::json!([]));
+    let request = test::TestRequest::get()
+        .uri("/api/v1/group")
+        .insert_header(admin.clone())
+        .to_request();
+    let groups: Vec<serde_json::Value> = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0]["name"], "grafana-admin");
+
+    // Claim name must not clash with standard claims
+    config.groups_claim = "sub".into();
+    assert!(Avanguard::builder()
+        .config(config)
+        .store(pool)
+        .build()
+        .is_err());
+}
@@ -13,7 +13,7 @@ export default defineConfig({
       '/avanguard': {
         target: 'http://127.0.0.1:8080/',
         changeOrigin: true,
-        rewrite: (path) => path.replace(/^\/avanguard/, ''),
+        rewrite: (path) => path.replace(/^\/avanguard/, '/api/v1'),
       },
     },
   },